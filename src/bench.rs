@@ -0,0 +1,50 @@
+// Fixed-depth search over a built-in set of positions. Every position starts from fresh tables,
+// so the total node count is a signature of the search: a non-functional change must not move it
+use std::time::Instant;
+use crate::{think, SearchLimits, SearchState};
+use crate::board::Board;
+
+pub const DEFAULT_DEPTH: i32 = 8;
+const HASH_MB: usize = 16;
+
+const POSITIONS: [&str; 16] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 8",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "8/8/8/4k3/8/8/3PK3/8 w - - 0 1",
+    "5k2/7R/4P2p/5K2/p1r2P1p/8/8/8 b - - 0 1",
+    "2K5/p7/7P/5pR1/8/5k2/r7/8 w - - 0 1",
+    "8/8/1p6/p1p5/P1P2k2/1P3p2/5K2/8 b - - 0 1",
+];
+
+// Prints the nodes of each position, then the total and the speed
+pub fn run(depth: i32) {
+    let limits = SearchLimits { depth: Some(depth), ..SearchLimits::default() };
+    let mut total_nodes = 0;
+    let mut total_ms = 0;
+    for (i, fen) in POSITIONS.iter().enumerate() {
+        let mut board = Board::from_fen(fen).expect("bench positions are valid");
+        // Fresh tables, killers and history for every position
+        let mut state = SearchState::new(HASH_MB);
+        state.search_info.quiet = true;
+        let timer = Instant::now();
+        let result = think(&mut board, &limits, timer, &mut state);
+        total_ms += timer.elapsed().as_millis() as u64;
+        let nodes = state.search_info.total_nodes();
+        total_nodes += nodes;
+        println!("Position {:>2}/{}: {:>10} nodes  bestmove {:<6} {}", i + 1, POSITIONS.len(), nodes, result.best_move, fen);
+    }
+    println!("===========================");
+    println!("Total time (ms) : {}", total_ms);
+    println!("Nodes searched  : {}", total_nodes);
+    println!("Nodes/second    : {}", total_nodes * 1000 / total_ms.max(1));
+}
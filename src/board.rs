@@ -10,6 +10,7 @@ use util::Score;
 use self::strum_macros::EnumIter;
 use self::strum::IntoEnumIterator;
 use crate::{board, PIECE_VALUES, MOBILITY_VALUES};
+use crate::zobrist;
 use crate::zobrist::{ZOBRIST_CASTLING,ZOBRIST_EP,ZOBRIST_PIECES,ZOBRIST_SIDE};
 
 pub const KNIGHT_ATTACKS: [u64; 64] =[0x0000000000020400, 0x0000000000050800, 0x00000000000a1100, 0x0000000000142200, 0x0000000000284400, 0x0000000000508800, 0x0000000000a01000, 0x0000000000402000,
@@ -29,13 +30,18 @@ pub const KING_ATTACKS: [u64; 64] =[0x0000000000000302, 0x0000000000000705, 0x00
 0x0302030000000000, 0x0705070000000000, 0x0e0a0e0000000000, 0x1c141c0000000000, 0x3828380000000000, 0x7050700000000000, 0xe0a0e00000000000, 0xc040c00000000000,
 0x0203000000000000, 0x0507000000000000, 0x0a0e000000000000, 0x141c000000000000, 0x2838000000000000, 0x5070000000000000, 0xa0e0000000000000, 0x40c0000000000000];
 // More sophisticated phase calculation based on remaining material
-pub const KNIGHT_PHASE: u32 = 1;
-pub const BISHOP_PHASE: u32 = 1;
-pub const ROOK_PHASE: u32 = 2;
-pub const QUEEN_PHASE: u32 = 4;
+pub const KNIGHT_PHASE: i32 = 1;
+pub const BISHOP_PHASE: i32 = 1;
+pub const ROOK_PHASE: i32 = 2;
+pub const QUEEN_PHASE: i32 = 4;
 
 // Total material at game start
-pub const TOTAL_PHASE: u32 = KNIGHT_PHASE * 4 + BISHOP_PHASE * 4 + ROOK_PHASE * 4 + QUEEN_PHASE * 2;
+pub const TOTAL_PHASE: i32 = KNIGHT_PHASE * 4 + BISHOP_PHASE * 4 + ROOK_PHASE * 4 + QUEEN_PHASE * 2;
+// 0 (opening) to 255 (endgame), with more material than the start position counting as the opening
+pub fn phase_from_count(phase_count: i32) -> u8 {
+    let phase_count = phase_count.clamp(0, TOTAL_PHASE);
+    ((phase_count * 255 + TOTAL_PHASE/2)/TOTAL_PHASE) as u8
+}
 // Enum for bitboard piece tables
 #[derive(Copy, Clone, PartialEq, Eq, EnumIter, Debug)]
 pub enum BBPiece {
@@ -71,6 +77,7 @@ pub struct Board {
     pub bitboards: [u64; 8], // 8 bitboards, accessed via the enum
     pub move_color: i8, // 1 for White, -1 for Black
     pub castling_rights: [bool; 4], // [White King, White Queen, Black King, Black Queen]
+    pub castling_rooks: [u8; 4], // start squares of the castling rooks, same order as castling_rights
     pub en_passant: Option<usize>,
     pub halfmove_clock: u8,
     pub fullmove_number: u16,
@@ -85,11 +92,22 @@ pub struct Board {
     pub position_history: Vec<u64>,
     pub pawn_position_history: Vec<u64>,
     pub phase: u8,
-    pub phase_count: u32, // Total phase count for evaluation
+    pub phase_count: i32, // Total phase count for evaluation, below zero with more material than the start position
     pub material_score: Score, // Material score for evaluation
 }
 
+pub const STARTPOS_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
 impl Board {
+    // A fully initialized board, Zobrist and pawn hashes included
+    pub fn from_fen(fen: &str) -> Result<Board, util::FenError> {
+        util::board_from_fen(fen)
+    }
+
+    pub fn startpos() -> Board {
+        Board::from_fen(STARTPOS_FEN).expect("the start position FEN is valid")
+    }
+
     // Bitboard util functions
     #[inline]
     pub fn get(&self, pieces: impl IntoIterator<Item = BBPiece>, square: impl Into<usize>) -> bool {
@@ -179,6 +197,80 @@ pub fn castling_rights_to_bits(castling_rights: &[bool; 4]) -> usize {
     (castling_rights[2] as usize) << 2 |
     (castling_rights[3] as usize) << 3
 }
+// Start squares of the castling rooks in standard chess
+pub const STANDARD_CASTLING_ROOKS: [u8; 4] = [Squares::H1 as u8, Squares::A1 as u8, Squares::H8 as u8, Squares::A8 as u8];
+// King and rook destinations of a castling move, the standard squares whatever the start files (Chess960)
+pub fn castling_destinations(king_from: usize, king_side: bool) -> (usize, usize) {
+    let rank = king_from / 8 * 8;
+    if king_side { (rank + 6, rank + 5) } else { (rank + 2, rank + 3) }
+}
+// Mask of the squares from a to b, both included
+fn squares_between(a: usize, b: usize) -> u64 {
+    let (low, high) = (a.min(b), a.max(b));
+    (u64::MAX >> (63 - high)) & (u64::MAX << low)
+}
+// Squares strictly between two squares on a rank, file or diagonal, empty when they are not aligned
+fn ray_between(a: usize, b: usize) -> u64 {
+    let (a_bb, b_bb) = (1u64 << a, 1u64 << b);
+    if rook_attacks(a, 0) & b_bb != 0 {
+        rook_attacks(a, b_bb) & rook_attacks(b, a_bb)
+    } else if bishop_attacks(a, 0) & b_bb != 0 {
+        bishop_attacks(a, b_bb) & bishop_attacks(b, a_bb)
+    } else {
+        0
+    }
+}
+// The whole rank, file or diagonal through two aligned squares
+fn line_through(a: usize, b: usize) -> u64 {
+    let ends = (1u64 << a) | (1u64 << b);
+    if rook_attacks(a, 0) & (1u64 << b) != 0 {
+        (rook_attacks(a, 0) & rook_attacks(b, 0)) | ends
+    } else if bishop_attacks(a, 0) & (1u64 << b) != 0 {
+        (bishop_attacks(a, 0) & bishop_attacks(b, 0)) | ends
+    } else {
+        0
+    }
+}
+// What the generator needs to keep only legal moves, worked out once per position
+pub(crate) struct Legality {
+    king: usize,
+    them: u64,
+    occupied: u64,
+    checkers: u64, // enemy pieces giving check
+    pinned: u64, // own pieces that are the only piece between the king and an enemy slider
+}
+impl Legality {
+    // Where pieces other than the king may go: anywhere, onto or in front of a single checker, nowhere in double check
+    fn targets(&self) -> u64 {
+        match self.checkers.count_ones() {
+            0 => u64::MAX,
+            1 => self.checkers | ray_between(self.king, self.checkers.trailing_zeros() as usize),
+            _ => 0,
+        }
+    }
+}
+// Castling moves are stored as the king taking its own rook, so the king and rook may start on any file
+fn castle(board: &mut Board, king_from: usize, rook_from: usize, king_side: bool) {
+    let color = if board.move_color == Color::White as i8 { BBPiece::White } else { BBPiece::Black };
+    let (king_to, rook_to) = castling_destinations(king_from, king_side);
+    // Lift both pieces first, the start and destination squares may overlap
+    board.set([BBPiece::King, color], king_from, false);
+    board.set([BBPiece::Rook, color], rook_from, false);
+    board.set([BBPiece::King, color], king_to, true);
+    board.set([BBPiece::Rook, color], rook_to, true);
+    for (piece, from, to) in [(BBPiece::King, king_from, king_to), (BBPiece::Rook, rook_from, rook_to)] {
+        board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(piece, color)][from];
+        board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(piece, color)][to];
+    }
+}
+// Debug builds recompute both hashes after every make/undo, catching a missed incremental update where it happens
+#[inline]
+fn debug_check_hashes(board: &Board) {
+    if cfg!(debug_assertions) {
+        assert_eq!(board.zobrist_hash, zobrist::zobrist_hash(board), "zobrist hash out of sync after {:?}", board.move_history.last());
+        assert_eq!(board.pawn_hash, zobrist::zobrist_pawn_hash(board), "pawn hash out of sync after {:?}", board.move_history.last());
+    }
+}
 // make move function (as UCI) - given a from and to square, move the piece to the new square, and empty the previous square (accepts square name inputs)
 // assumes that a move is legal, tracks other FEN changes
 pub fn make_null_move(board: &mut Board) -> Result<(), String> {
@@ -210,38 +302,19 @@ pub fn make_move(board: &mut Board, _move: & Move) -> Result<(), String> {
             // King moved, update castling rights
             board.castling_rights[(1-board.move_color) as usize] = false; // First castling rights for color
             board.castling_rights[(2-board.move_color) as usize] = false; // Second castling rights for color
-        } if board.get([BBPiece::Rook], from_index) {
-            // Rook moved, update castling rights
-            // Todo optimize with color var
-            if board.move_color == Color::White as i8 {
-                if from_index == Squares::A1 as u8 {
-                    board.castling_rights[1] = false; // White Queen-side
-                } else if from_index == Squares::H1 as u8 {
-                    board.castling_rights[0] = false; // White King-side
-                }
-            } else {
-                if from_index == Squares::A8 as u8 {
-                    board.castling_rights[3] = false; // Black Queen-side
-                } else if from_index == Squares::H8 as u8 {
-                    board.castling_rights[2] = false; // Black King-side
-                }
-            }
-        } if board.get([BBPiece::Rook], to_index) {
-            // Capturing a rook possibly on a corner square, update castling rights for the opponent
-            if to_index == Squares::A1 as u8 {
-                board.castling_rights[1] = false; // White Queen-side
-            } else if to_index == Squares::H1 as u8 {
-                board.castling_rights[0] = false; // White King-side
-            } else if to_index == Squares::A8 as u8 {
-                board.castling_rights[3] = false; // Black Queen-side
-            } else if to_index == Squares::H8 as u8 {
-                board.castling_rights[2] = false; // Black King-side
+        }
+        // A castling rook that moves or gets captured takes its castling right with it
+        for right in 0..4 {
+            if board.castling_rooks[right] == from_index || board.castling_rooks[right] == to_index {
+                board.castling_rights[right] = false;
             }
         }
     }
     board.zobrist_hash ^= ZOBRIST_CASTLING[castling_rights_to_bits(&board.castling_rights)];
     let mut captured: Option<(BBPiece, BBPiece)> = None;
-    if from_index != to_index {
+    if from_index != to_index && flags & 0x2 != 0 && flags & 0xC == 0 { // Castling
+        castle(board, from_index as usize, to_index as usize, flags == MoveFlag::KingCastle as u8);
+    } else if from_index != to_index {
         // Handle capture and move the piece
         for color in [BBPiece::White, BBPiece::Black] {
             if board.get([color], to_index) {
@@ -345,25 +418,6 @@ pub fn make_move(board: &mut Board, _move: & Move) -> Result<(), String> {
             board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(BBPiece::Pawn, opp_color)][captured_pawn_index as usize];
             board.pawn_hash ^= ZOBRIST_PIECES[zobrist_piece_index(BBPiece::Pawn, opp_color)][captured_pawn_index as usize];
         }
-        if flags & 0x2 != 0 && flags & 0xC == 0 { // Castling
-            if from_index == Squares::E1 as u8 && to_index == Squares::G1 as u8 { // White King-side castle
-                board.move_piece([BBPiece::Rook, BBPiece::White], Squares::H1, Squares::F1); 
-                board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(BBPiece::Rook, BBPiece::White)][Squares::H1 as usize];
-                board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(BBPiece::Rook, BBPiece::White)][Squares::F1 as usize];
-            } else if from_index == Squares::E1 as u8 && to_index == Squares::C1 as u8 { // White Queen-side castle
-                board.move_piece([BBPiece::Rook, BBPiece::White], Squares::A1, Squares::D1);
-                board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(BBPiece::Rook, BBPiece::White)][Squares::A1 as usize];
-                board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(BBPiece::Rook, BBPiece::White)][Squares::D1 as usize];
-            } else if from_index == Squares::E8 as u8 && to_index == Squares::G8 as u8 { // Black King-side castle
-                board.move_piece([BBPiece::Rook, BBPiece::Black], Squares::H8, Squares::F8); 
-                board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(BBPiece::Rook, BBPiece::Black)][Squares::H8 as usize];
-                board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(BBPiece::Rook, BBPiece::Black)][Squares::F8 as usize];
-            } else if from_index == Squares::E8 as u8 && to_index == Squares::C8 as u8 { // Black Queen-side castle
-                board.move_piece([BBPiece::Rook, BBPiece::Black], Squares::A8, Squares::D8); 
-                board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(BBPiece::Rook, BBPiece::Black)][Squares::A8 as usize];
-                board.zobrist_hash ^= ZOBRIST_PIECES[zobrist_piece_index(BBPiece::Rook, BBPiece::Black)][Squares::D8 as usize];
-            }
-        }
     }
     // Update other board state information
     board.move_color *= -1;
@@ -396,6 +450,7 @@ pub fn make_move(board: &mut Board, _move: & Move) -> Result<(), String> {
         }
     }
     board.move_history.push(*_move);
+    debug_check_hashes(board);
     Ok(())
 }
 pub fn undo_move(board: &mut Board) -> Result<(), String> {
@@ -480,31 +535,12 @@ pub fn undo_move(board: &mut Board) -> Result<(), String> {
                 board.add_score(BBPiece::White, BBPiece::Pawn); // Update material score and phase
             }
         } else if flags & 0x2 != 0 && flags & 0xC == 0 {
-            // Undo castling
-            board.move_piece([BBPiece::King, if board.move_color == Color::White as i8 { BBPiece::White } else { BBPiece::Black }], to_index, from_index);
-            if board.move_color == Color::White as i8 {
-                board.set([BBPiece::White], to_index, false);
-                board.set([BBPiece::White], from_index, true);
-            } else {
-                board.set([BBPiece::Black], to_index, false);
-                board.set([BBPiece::Black], from_index, true);
-            }
-            // Undo rook move
-            match (from_index, to_index) {
-                (x, y) if x == Squares::E1 as u8 && y == Squares::G1 as u8 => {
-                    board.move_piece([BBPiece::Rook, BBPiece::White], Squares::F1, Squares::H1);
-                }
-                (x, y) if x == Squares::E1 as u8 && y == Squares::C1 as u8 => {
-                    board.move_piece([BBPiece::Rook, BBPiece::White], Squares::D1, Squares::A1);
-                }
-                (x, y) if x == Squares::E8 as u8 && y == Squares::G8 as u8 => {
-                    board.move_piece([BBPiece::Rook, BBPiece::Black], Squares::F8, Squares::H8);
-                }
-                (x, y) if x == Squares::E8 as u8 && y == Squares::C8 as u8 => {
-                    board.move_piece([BBPiece::Rook, BBPiece::Black], Squares::D8, Squares::A8);
-                }
-                _ => {}
-            }
+            // Undo castling, the king and rook go back to the from and to squares
+            let (king_to, rook_to) = castling_destinations(from_index as usize, flags == MoveFlag::KingCastle as u8);
+            board.set([BBPiece::King, color], king_to, false);
+            board.set([BBPiece::Rook, color], rook_to, false);
+            board.set([BBPiece::King, color], from_index, true);
+            board.set([BBPiece::Rook, color], to_index, true);
         } else {
             // Normal move
             for piece in [BBPiece::Pawn, BBPiece::Knight, BBPiece::Bishop, BBPiece::Rook, BBPiece::Queen, BBPiece::King] {
@@ -536,6 +572,7 @@ pub fn undo_move(board: &mut Board) -> Result<(), String> {
             }
         }
     }
+    debug_check_hashes(board);
     Ok(())
 }
 fn rook_attacks(square: usize, occupancy: u64) -> u64
@@ -554,6 +591,8 @@ fn bishop_attacks(square: usize, occupancy: u64) -> u64
     let index = hash as usize + magic.offset;
     magic::BISHOP_ATTACKS[index]
 }
+// Moves a losing capture from the capture scores (90000+) below killers and history
+const BAD_CAPTURE_PENALTY: i32 = 200000;
 impl Board {
     pub fn get_ordered_moves(&mut self, is_generated: bool, legal_only: bool, captures_only: bool, tt_move: Option<util::Move>, killer_moves: &[util::Move; 2], history: &[[i16; 64]; 64]) -> util::MoveStack {
         if !is_generated{
@@ -565,10 +604,18 @@ impl Board {
         }
         // Score moves based on multiple criteria
         else
-        {_moves.score_moves(|m: &Move| m.score_move(m, tt_move, killer_moves, self.attacking_piece(m), self.captured_piece(m), history));
+        {_moves.score_moves(|m: &Move| {
+            let score = m.score_move(m, tt_move, killer_moves, self.attacking_piece(m), self.captured_piece(m), history);
+            // Captures that lose material on the exchange go after the quiet moves
+            if m.flags() & MoveFlag::Capture as u8 != 0 && Some(*m) != tt_move && self.see(m) < 0 {
+                score - BAD_CAPTURE_PENALTY
+            } else {
+                score
+            }
+        });
         }_moves
     }
-    fn attacking_piece(&self, m: &Move) -> Option<BBPiece> {
+    pub(crate) fn attacking_piece(&self, m: &Move) -> Option<BBPiece> {
         if m.flags() & MoveFlag::Capture as u8 != 0 {
             let from_square = m.from_square() as usize;
             for (i, &bb) in self.bitboards.iter().enumerate() {
@@ -581,7 +628,7 @@ impl Board {
         }
         None
     }
-    fn captured_piece(&self, m: &Move) -> Option<BBPiece> {
+    pub(crate) fn captured_piece(&self, m: &Move) -> Option<BBPiece> {
         if m.flags() & MoveFlag::Capture as u8 != 0 {
             let to_square = m.to_square() as usize;
             for (i, &bb) in self.bitboards.iter().enumerate() {
@@ -594,21 +641,65 @@ impl Board {
         }
         None
     }
-    // Pseudolegal move generator
-    fn is_legal_move(&mut self, m: &Move) -> bool
-    {
-        board::make_move(self, m);
-        let is_legal = !self.king_is_attacked();
-        board::undo_move(self);
-        is_legal
+    fn legality(&self) -> Legality {
+        let (us, them) = if self.move_color == Color::White as i8 {
+            (self.bitboards[BBPiece::White as usize], self.bitboards[BBPiece::Black as usize])
+        } else {
+            (self.bitboards[BBPiece::Black as usize], self.bitboards[BBPiece::White as usize])
+        };
+        let king = (self.bitboards[BBPiece::King as usize] & us).trailing_zeros() as usize;
+        let occupied = us | them;
+        let queens = self.bitboards[BBPiece::Queen as usize];
+        let mut snipers = them & ((rook_attacks(king, 0) & (self.bitboards[BBPiece::Rook as usize] | queens))
+            | (bishop_attacks(king, 0) & (self.bitboards[BBPiece::Bishop as usize] | queens)));
+        let mut pinned = 0;
+        while snipers != 0 {
+            let blockers = ray_between(king, util::bb_gs_low_bit(&mut snipers)) & occupied;
+            if blockers.count_ones() == 1 {
+                pinned |= blockers & us;
+            }
+        }
+        Legality { king, them, occupied, checkers: self.attackers_to(king, occupied) & them, pinned }
+    }
+    // Whether a pseudo-legal move leaves the own king safe, without playing it
+    fn is_legal(&self, m: &Move, legality: &Legality) -> bool {
+        let from = m.from_square() as usize;
+        let to = m.to_square() as usize;
+        let flags = m.flags();
+        if flags == MoveFlag::KingCastle as u8 || flags == MoveFlag::QueenCastle as u8 {
+            // The path was checked when generating, the rook may still uncover the king's destination
+            let (king_to, rook_to) = castling_destinations(from, flags == MoveFlag::KingCastle as u8);
+            let occupied = (legality.occupied & !(1u64 << from) & !(1u64 << to)) | (1u64 << king_to) | (1u64 << rook_to);
+            return legality.checkers == 0 && self.attackers_to(king_to, occupied) & legality.them == 0;
+        }
+        if from == legality.king {
+            // Without the king on its square, so it can not step back along the line of a slider
+            return self.attackers_to(to, legality.occupied & !(1u64 << from)) & legality.them == 0;
+        }
+        if flags == MoveFlag::EnPassant as u8 {
+            // Two pawns leave the rank at once, which can uncover the king, so look at the position after the move
+            let captured = if self.move_color == Color::White as i8 { to - 8 } else { to + 8 };
+            let occupied = (legality.occupied & !(1u64 << from) & !(1u64 << captured)) | (1u64 << to);
+            return self.attackers_to(legality.king, occupied) & legality.them & !(1u64 << captured) == 0;
+        }
+        legality.pinned & (1u64 << from) == 0 || line_through(legality.king, from) & (1u64 << to) != 0
+    }
+    // Whether a pseudo-legal move, like one from the TT, is legal here
+    pub fn is_legal_move(&self, m: &Move) -> bool {
+        self.is_legal(m, &self.legality())
     }
-    fn add_move(&mut self, m: &Move, legal_only: bool)
+    fn add_move(&mut self, m: &Move, legal_only: Option<&Legality>)
     {
-        if !legal_only || self.is_legal_move(m)
+        if legal_only.is_none_or(|legality| self.is_legal(m, legality))
         {
             self.moves.push(*m);
         }
     }
+    // The legal moves of the position, also left in `moves`
+    pub fn legal_moves(&mut self) -> util::MoveStack {
+        self.gen_moves(true, false);
+        self.moves
+    }
     pub fn captures_only(&mut self)
     {
         self.moves.retain(|m| m.flags() & MoveFlag::Capture as u8 != 0);
@@ -622,6 +713,7 @@ impl Board {
     } else {
         self.material_score -= PIECE_VALUES[piece as usize];
     }
+    // Promotions can take the count below zero, `phase_from_count` clamps it
     self.phase_count -= match piece {
         BBPiece::Pawn => 0,
         BBPiece::Knight => KNIGHT_PHASE,
@@ -651,10 +743,22 @@ impl Board {
         self.calculate_phase();
     }
     fn calculate_phase(&mut self) {
-        self.phase = ((self.phase_count * 255 + TOTAL_PHASE/2)/TOTAL_PHASE) as u8; 
+        self.phase = phase_from_count(self.phase_count);
     }
+    // Pseudo-legal moves, or with `legal_only` only the legal ones: evasions when in check, and no
+    // moves of pinned pieces off their pin line
     pub fn gen_moves(&mut self, legal_only: bool, captures_only: bool) {
+        self.generate(legal_only, true, !captures_only);
+    }
+    // The moves `gen_moves(legal_only, true)` leaves out: quiet moves, castling and promotions without a capture
+    pub fn gen_quiet_moves(&mut self, legal_only: bool) {
+        self.generate(legal_only, false, true);
+    }
+    fn generate(&mut self, legal_only: bool, captures: bool, quiets: bool) {
         self.moves.clear();
+        let legality = legal_only.then(|| self.legality());
+        let legal_only = legality.as_ref();
+        let targets = legality.as_ref().map_or(u64::MAX, Legality::targets);
         let white = self.move_color == Color::White as i8;
         let color_bb: BBPiece = if white {
             BBPiece::White
@@ -663,7 +767,12 @@ impl Board {
         };
         let combined_bb: u64 = self.combined([BBPiece::White, BBPiece::Black], false);
         let empty_bb = !combined_bb;
+        // The destination squares of the kinds of move asked for
+        let kinds = if captures { self.bitboards[1 - (color_bb as usize)] } else { 0 } | if quiets { empty_bb } else { 0 };
         for i in [BBPiece::Pawn, BBPiece::Knight, BBPiece::Bishop, BBPiece::Rook, BBPiece::Queen, BBPiece::King] {
+            if targets == 0 && i != BBPiece::King {
+                continue; // double check, only the king can move
+            }
             let mut pc_bb = self.combined([i, color_bb], true);
             // for all - generate start/end squares, get proper flag
             match i {
@@ -685,14 +794,19 @@ impl Board {
                         //code here to add possible en passant square
                         captures_left  = ((pc_bb & not_a_file) << 7) & black_pieces;
                         captures_right = ((pc_bb & not_h_file) << 9) & black_pieces;
-                        if let Some(ep_sq) = self.en_passant {
+                        // When in check pieces can only capture the checker or step in its way
+                        single_push &= targets;
+                        double_push &= targets;
+                        captures_left &= targets & kinds;
+                        captures_right &= targets & kinds;
+                        if let Some(ep_sq) = self.en_passant.filter(|_| captures) {
                             let ep_bb = 1u64 << ep_sq;
                             // Left en passant: pawn must be on the file to the right of ep_sq and able to capture left
                             en_passant_left = ((pc_bb & not_a_file) << 7) & ep_bb;
                             // Right en passant: pawn must be on the file to the left of ep_sq and able to capture right
                             en_passant_right = ((pc_bb & not_h_file) << 9) & ep_bb;
                         }   
-                        while single_push != 0 && !captures_only // pawn pushes are never captures
+                        while single_push != 0 && quiets // pawn pushes are never captures
                         {
                             let to_sq = util::bb_gs_low_bit(&mut single_push) as u8;
                             if to_sq >= 56 // first or last rank
@@ -727,7 +841,7 @@ impl Board {
                             }
 
                         }
-                        while double_push != 0 && !captures_only
+                        while double_push != 0 && quiets
                         {
                             let to_sq = util::bb_gs_low_bit(&mut double_push) as u8;
                             self.add_move(&Move::from_parts(
@@ -830,14 +944,19 @@ impl Board {
                         let white_pieces = self.bitboards[BBPiece::White as usize];
                         captures_left  = ((pc_bb & not_h_file) >> 7) & white_pieces;
                         captures_right = ((pc_bb & not_a_file) >> 9) & white_pieces;
-                        if let Some(ep_sq) = self.en_passant {
+                        // When in check pieces can only capture the checker or step in its way
+                        single_push &= targets;
+                        double_push &= targets;
+                        captures_left &= targets & kinds;
+                        captures_right &= targets & kinds;
+                        if let Some(ep_sq) = self.en_passant.filter(|_| captures) {
                             let ep_bb = 1u64 << ep_sq;
                             // Left en passant: pawn must be on the file to the right of ep_sq and able to capture left
                             en_passant_left = ((pc_bb & not_h_file) >> 7) & ep_bb;
                             // Right en passant: pawn must be on the file to the left of ep_sq and able to capture right
                             en_passant_right = ((pc_bb & not_a_file) >> 9) & ep_bb;
                         }   
-                        while single_push != 0 && !captures_only
+                        while single_push != 0 && quiets
                         {
                             let to_sq = util::bb_gs_low_bit(&mut single_push) as u8;
                             if to_sq <= 7 // first or last rank
@@ -872,7 +991,7 @@ impl Board {
                             }
 
                         }
-                        while double_push != 0 && !captures_only
+                        while double_push != 0 && quiets
                         {
                             let to_sq = util::bb_gs_low_bit(&mut double_push) as u8;
                             self.add_move(&Move::from_parts(
@@ -974,25 +1093,18 @@ impl Board {
                     while _square != 64 {
                         // Generate knight moves
                         // Knight moves are L-shaped, 2 squares in one direction and 1 square perpendicular
-                        let mut attacks = KNIGHT_ATTACKS[_square] & !self.bitboards[color_bb as usize];
+                        let mut attacks = KNIGHT_ATTACKS[_square] & targets & kinds;
                         let mut move_square = util::bb_gs_low_bit(&mut attacks);
                         while move_square != 64 {
                                     let mut flags = MoveFlag::Quiet as u8;
                                     if util::bb_get(self.bitboards[1-(color_bb as usize)], move_square as usize) {
                                         flags = MoveFlag::Capture as u8; // Capture if opponent piece
-                                        self.add_move(&Move::from_parts(
+                                    }
+                                    self.add_move(&Move::from_parts(
                                         _square as u8,
                                         move_square as u8,
                                         flags,
-                                        ), legal_only);
-                                    }
-                                    else if (!captures_only) {
-                                        self.add_move(&Move::from_parts(
-                                            _square as u8,
-                                            move_square as u8,
-                                            flags,
-                                        ), legal_only);
-                                    }
+                                    ), legal_only);
                                     move_square = util::bb_gs_low_bit(&mut attacks);
                                     
                                 }
@@ -1004,7 +1116,7 @@ impl Board {
                     // Queen move gen but just diagonals
                     let mut _square = util::bb_gs_low_bit(&mut pc_bb);
                     while _square != 64 {
-                        self.gen_sliding_moves(_square as usize, legal_only, captures_only, false, true);
+                        self.gen_sliding_moves(_square as usize, legal_only, false, true, targets & kinds);
                         _square = util::bb_gs_low_bit(&mut pc_bb);
                     }
                 }
@@ -1013,7 +1125,7 @@ impl Board {
                     // Queen move gen but just horizontals
                     let mut _square = util::bb_gs_low_bit(&mut pc_bb);
                     while _square != 64 {
-                        self.gen_sliding_moves(_square as usize, legal_only, captures_only, true, false);
+                        self.gen_sliding_moves(_square as usize, legal_only, true, false, targets & kinds);
                         _square = util::bb_gs_low_bit(&mut pc_bb);
                     }
                 }
@@ -1024,7 +1136,7 @@ impl Board {
                     // If hitting a piece, check if it's an opponent piece to capture
                     let mut _square = util::bb_gs_low_bit(&mut pc_bb);
                     while _square != 64 {
-                        self.gen_sliding_moves(_square as usize, legal_only, captures_only, true, true);
+                        self.gen_sliding_moves(_square as usize, legal_only, true, true, targets & kinds);
                         _square = util::bb_gs_low_bit(&mut pc_bb);
                     }
                 }
@@ -1032,48 +1144,38 @@ impl Board {
                     // Generate king moves
                     let mut _square = util::bb_gs_low_bit(&mut pc_bb);
                     // If castling rights exist, check if no pieces are in between the king and rook
-                    if !captures_only {
-                        if self.move_color == Color::White as i8 {
-                            if self.castling_rights[0] && _square == Squares::E1 as usize && !util::bb_get(combined_bb, Squares::F1 as usize) && !util::bb_get(combined_bb, Squares::G1 as usize) {
-                                // King-side castle
-                                self.add_move(&Move::from_parts(_square as u8, Squares::G1 as u8, MoveFlag::KingCastle as u8), legal_only);
+                    if quiets && _square != 64 {
+                        let first_right = if self.move_color == Color::White as i8 { 0 } else { 2 };
+                        for right in first_right..first_right + 2 {
+                            let rook_square = self.castling_rooks[right] as usize;
+                            if !self.castling_rights[right] || rook_square / 8 != _square / 8 || !self.get([BBPiece::Rook, color_bb], rook_square) {
+                                continue;
                             }
-                            if self.castling_rights[1] && _square == Squares::E1 as usize && !util::bb_get(combined_bb, Squares::D1 as usize) && !util::bb_get(combined_bb, Squares::C1 as usize) && !util::bb_get(combined_bb, Squares::B1 as usize) {
-                                // Queen-side castle
-                                self.add_move(&Move::from_parts(_square as u8, Squares::C1 as u8, MoveFlag::QueenCastle as u8), legal_only);
-                            }
-                        } else {
-                            if self.castling_rights[2] && _square == Squares::E8 as usize && !util::bb_get(combined_bb, Squares::F8 as usize) && !util::bb_get(combined_bb, Squares::G8 as usize) {
-                                // King-side castle
-                                self.add_move(&Move::from_parts(_square as u8, Squares::G8 as u8, MoveFlag::KingCastle as u8), legal_only);
-                            }
-                            if self.castling_rights[3] && _square == Squares::E8 as usize && !util::bb_get(combined_bb, Squares::D8 as usize) && !util::bb_get(combined_bb, Squares::C8 as usize) && !util::bb_get(combined_bb, Squares::B8 as usize) {
-                                // Queen-side castle
-                                self.add_move(&Move::from_parts(_square as u8, Squares::C8 as u8, MoveFlag::QueenCastle as u8), legal_only);
+                            let king_side = right % 2 == 0;
+                            let (king_to, rook_to) = castling_destinations(_square, king_side);
+                            // Everything the king and rook pass over must be empty, apart from the two of them
+                            let others = combined_bb & !(1u64 << _square) & !(1u64 << rook_square);
+                            if (squares_between(_square, king_to) | squares_between(rook_square, rook_to)) & others == 0
+                                && !self.castling_path_attacked(_square, king_to) {
+                                let flag = if king_side { MoveFlag::KingCastle } else { MoveFlag::QueenCastle };
+                                self.add_move(&Move::from_parts(_square as u8, rook_square as u8, flag as u8), legal_only);
                             }
                         }
                     }
                         while _square != 64 {
                         // Generate king moves
-                        let mut attacks = KING_ATTACKS[_square] & !self.bitboards[color_bb as usize];
+                        let mut attacks = KING_ATTACKS[_square] & kinds;
                         let mut move_square = util::bb_gs_low_bit(&mut attacks);
                         while move_square != 64 {
                                     let mut flags = MoveFlag::Quiet as u8;
                                     if util::bb_get(self.bitboards[1-(color_bb as usize)], move_square as usize) {
                                         flags = MoveFlag::Capture as u8; // Capture if opponent piece
-                                        self.add_move(&Move::from_parts(
+                                    }
+                                    self.add_move(&Move::from_parts(
                                         _square as u8,
                                         move_square as u8,
                                         flags,
-                                        ), legal_only);
-                                    }
-                                    else if !captures_only {
-                                        self.add_move(&Move::from_parts(
-                                            _square as u8,
-                                            move_square as u8,
-                                            flags,
-                                        ), legal_only);
-                                    }
+                                    ), legal_only);
                                     move_square = util::bb_gs_low_bit(&mut attacks);
                                 }
                         _square = util::bb_gs_low_bit(&mut pc_bb);
@@ -1083,7 +1185,7 @@ impl Board {
             }
         }
     }
-    fn gen_sliding_moves(&mut self, idx: usize, legal_only: bool, captures_only: bool, orth: bool, diag: bool) {
+    fn gen_sliding_moves(&mut self, idx: usize, legal_only: Option<&Legality>, orth: bool, diag: bool, target_mask: u64) {
         let color_bb: BBPiece = if self.move_color == Color::White as i8 {
             BBPiece::White
         } else {
@@ -1102,7 +1204,7 @@ impl Board {
         }
 
         // Remove own pieces from attack set
-        let targets = attacks & !own_pieces;
+        let targets = attacks & !own_pieces & target_mask;
 
         // Iterate over all target squares
         let mut targets_bb = targets;
@@ -1113,9 +1215,7 @@ impl Board {
             } else {
                 MoveFlag::Quiet as u8
             };
-            if flag == MoveFlag::Capture as u8 || !captures_only {
-                self.add_move(&Move::from_parts(idx as u8, to as u8, flag), legal_only);
-            }
+            self.add_move(&Move::from_parts(idx as u8, to as u8, flag), legal_only);
             targets_bb &= targets_bb - 1; // Clear the lowest set bit
         }
     }
@@ -1127,33 +1227,23 @@ impl Board {
             BBPiece::White
         };
         let mut king_bb = self.combined([BBPiece::King, color_bb], true);
-        if let Some(_move) = self.move_history.last() {
-            if _move.flags() & 0x2 != 0 && _move.flags() & 0xC == 0 { // Castling
-            // Get squares moved through
-            let from_index = _move.from_square();
-            let to_index = _move.to_square();
-            if from_index == Squares::E1 as u8 && to_index == Squares::G1 as u8 { // White King-side castle
-                if self.square_is_attacked(Squares::E1 as usize) || self.square_is_attacked(Squares::F1 as usize) {
-                return true;
-                }
-            } else if from_index == Squares::E1 as u8 && to_index == Squares::C1 as u8 { // White Queen-side castle
-                if self.square_is_attacked(Squares::E1 as usize) || self.square_is_attacked(Squares::D1 as usize) {
-                return true;
-                }
-            } else if from_index == Squares::E8 as u8 && to_index == Squares::G8 as u8 { // Black King-side castle
-                if self.square_is_attacked(Squares::E8 as usize) || self.square_is_attacked(Squares::F8 as usize) {
-                return true;
-                } 
-            } else if from_index == Squares::E8 as u8 && to_index == Squares::C8 as u8 { // Black Queen-side castle
-                if self.square_is_attacked(Squares::E8 as usize) || self.square_is_attacked(Squares::D8 as usize) {
-                return true;
-                }
-            }
-            }
-        }
         let square = util::bb_gs_low_bit(&mut king_bb);
         return self.square_is_attacked(square);
     }
+    // Whether the king would castle out of or through check, looked at before the move as the
+    // castling rook may land between an attacker and the squares the king crossed (Chess960)
+    fn castling_path_attacked(&mut self, king_from: usize, king_to: usize) -> bool {
+        self.move_color = -self.move_color; // square_is_attacked looks for attacks by the side to move
+        let mut path = squares_between(king_from, king_to);
+        let mut attacked = false;
+        let mut square = util::bb_gs_low_bit(&mut path);
+        while square != 64 && !attacked {
+            attacked = self.square_is_attacked(square);
+            square = util::bb_gs_low_bit(&mut path);
+        }
+        self.move_color = -self.move_color;
+        attacked
+    }
     // Checks if a square is attacked
     pub fn square_is_attacked(&self, square: usize) -> bool {
         // Check if the square is attacked by any piece of current player (i.e., can we take the opponent king after they made their move)
@@ -1345,4 +1435,119 @@ impl Board {
         // Count legal moves (attacks that don't capture own pieces)
         attacks
     }
+    // All pieces of both colors attacking a square, sliders only looking through squares missing from `occupied`
+    pub fn attackers_to(&self, square: usize, occupied: u64) -> u64 {
+        let target = 1u64 << square;
+        let not_a_file = 0xfefefefefefefefe;
+        let not_h_file = 0x7f7f7f7f7f7f7f7f;
+        // The squares a pawn of each color attacks this square from
+        let white_pawns = ((target >> 9) & not_h_file) | ((target >> 7) & not_a_file);
+        let black_pawns = ((target << 7) & not_h_file) | ((target << 9) & not_a_file);
+        let pawns = self.bitboards[BBPiece::Pawn as usize];
+        let mut attackers = (white_pawns & pawns & self.bitboards[BBPiece::White as usize])
+            | (black_pawns & pawns & self.bitboards[BBPiece::Black as usize]);
+        for piece in [BBPiece::Knight, BBPiece::Bishop, BBPiece::Rook, BBPiece::Queen, BBPiece::King] {
+            attackers |= self.get_piece_attacks(piece as usize, square, occupied) & self.bitboards[piece as usize];
+        }
+        attackers & occupied
+    }
+    fn piece_type_on(&self, square: usize) -> Option<BBPiece> {
+        (BBPiece::Pawn as usize..=BBPiece::King as usize)
+            .find(|&piece| util::bb_get(self.bitboards[piece], square))
+            .map(BBPiece::from)
+    }
+    // Whether a move checks the enemy king, directly or by uncovering a slider. Castling is left out
+    pub fn gives_check(&self, m: &Move) -> bool {
+        let flags = m.flags();
+        if flags == MoveFlag::KingCastle as u8 || flags == MoveFlag::QueenCastle as u8 {
+            return false;
+        }
+        let white = self.move_color == Color::White as i8;
+        let (us, them) = if white {
+            (self.bitboards[BBPiece::White as usize], self.bitboards[BBPiece::Black as usize])
+        } else {
+            (self.bitboards[BBPiece::Black as usize], self.bitboards[BBPiece::White as usize])
+        };
+        let king = (self.bitboards[BBPiece::King as usize] & them).trailing_zeros() as usize;
+        let from = m.from_square() as usize;
+        let to = m.to_square() as usize;
+        let mut occupied = (self.combined([BBPiece::White, BBPiece::Black], false) & !(1u64 << from)) | (1u64 << to);
+        if flags == MoveFlag::EnPassant as u8 {
+            occupied &= !(1u64 << if white { to - 8 } else { to + 8 });
+        }
+        let mut piece = self.piece_type_on(from).unwrap_or(BBPiece::Pawn);
+        if flags & 8 != 0 {
+            piece = BBPiece::from(BBPiece::Knight as usize + (flags & 0x3) as usize);
+        }
+        let to_bb = 1u64 << to;
+        let (not_a_file, not_h_file) = (0xfefefefefefefefeu64, 0x7f7f7f7f7f7f7f7fu64);
+        let attacks = match piece {
+            BBPiece::Pawn if white => ((to_bb & not_a_file) << 7) | ((to_bb & not_h_file) << 9),
+            BBPiece::Pawn => ((to_bb & not_h_file) >> 7) | ((to_bb & not_a_file) >> 9),
+            BBPiece::King => 0,
+            _ => self.get_piece_attacks(piece as usize, to, occupied),
+        };
+        let queens = self.bitboards[BBPiece::Queen as usize];
+        let discovered = us & !(1u64 << from)
+            & ((rook_attacks(king, occupied) & (self.bitboards[BBPiece::Rook as usize] | queens))
+                | (bishop_attacks(king, occupied) & (self.bitboards[BBPiece::Bishop as usize] | queens)));
+        attacks & (1u64 << king) != 0 || discovered != 0
+    }
+    // Static exchange evaluation: the material the side to move wins on the target square of `m` when
+    // both sides keep recapturing with their least valuable piece, x-ray attackers included. Pins are ignored
+    pub fn see(&self, m: &Move) -> i32 {
+        let flags = m.flags();
+        if flags == MoveFlag::KingCastle as u8 || flags == MoveFlag::QueenCastle as u8 {
+            return 0;
+        }
+        let value = |piece: BBPiece| PIECE_VALUES[piece as usize].taper(self.phase);
+        let from = m.from_square() as usize;
+        let to = m.to_square() as usize;
+        let mut occupied = self.combined([BBPiece::White, BBPiece::Black], false) & !(1u64 << from);
+        let mut gain = [0i32; 32];
+        let mut attacker = self.piece_type_on(from).unwrap_or(BBPiece::Pawn);
+        if flags == MoveFlag::EnPassant as u8 {
+            gain[0] = value(BBPiece::Pawn);
+            let captured_square = if self.move_color == Color::White as i8 { to - 8 } else { to + 8 };
+            occupied &= !(1u64 << captured_square);
+        } else if flags & MoveFlag::Capture as u8 != 0 {
+            gain[0] = self.piece_type_on(to).map_or(0, value);
+        }
+        if flags & 8 != 0 {
+            // The pawn turns into the promotion piece, which is then the one standing on the square
+            attacker = BBPiece::from(BBPiece::Knight as usize + (flags & 0x3) as usize);
+            gain[0] += value(attacker) - value(BBPiece::Pawn);
+        }
+
+        let mut color = if self.move_color == Color::White as i8 { BBPiece::Black } else { BBPiece::White };
+        let mut depth = 0;
+        loop {
+            depth += 1;
+            // Score if the side to move now takes the piece that captured last
+            gain[depth] = value(attacker) - gain[depth - 1];
+            if (-gain[depth - 1]).max(gain[depth]) < 0 || depth == gain.len() - 1 {
+                break;
+            }
+            // Recomputed as captures open up x-rays behind the pieces that already took part
+            let attackers = self.attackers_to(to, occupied);
+            let own = attackers & self.bitboards[color as usize];
+            let Some(next) = (BBPiece::Pawn as usize..=BBPiece::King as usize).find(|&p| own & self.bitboards[p] != 0) else {
+                break;
+            };
+            let next = BBPiece::from(next);
+            // The king can not capture onto a square the other side still attacks
+            if next == BBPiece::King && attackers & !own != 0 {
+                break;
+            }
+            let mut piece_bb = own & self.bitboards[next as usize];
+            occupied &= !(1u64 << util::bb_gs_low_bit(&mut piece_bb));
+            attacker = next;
+            color = if color == BBPiece::White { BBPiece::Black } else { BBPiece::White };
+        }
+        while depth > 1 {
+            depth -= 1;
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+        }
+        gain[0]
+    }
 }
@@ -1,97 +1,11 @@
 #![allow(unused)]
-use core::hash;
-use std::cmp::max;
 use std::env;
-use std::fmt::Display;
-use std::fs::File;
-use std::io::BufWriter;
 use std::io::Write;
-use crate::magic::ROOK_MAGICS;
-use crate::table::PawnTable;
-use crate::table::{TranspositionTable, TTEntry, Bound};
-use crate::util::Move;
-use util::{Score, MoveStack};
-mod board;
-mod util;
-mod magic;
-mod zobrist;
-mod table;
-mod tuner;
-mod tunereval;
-pub const PIECE_VALUES: [Score; 8] = [
-    Score::new(0,0), // Empty
-    Score::new(0,0), // None
-    Score::new(73, 109), // Pawn
-    Score::new(306, 314), // Knight
-    Score::new(362, 325), // Bishop
-    Score::new(457, 625), // Rook
-    Score::new(1100, 1010), // Queen
-    Score::new(100000, 100000) // King
-];// === Mobility Weights ===
-pub const MOBILITY_VALUES: [Score; 8] = [
-    Score::new(0,0), Score::new(0,0), Score::new(0,0),
-    Score::new(9, 11), // Knight
-    Score::new(4, 10), // Bishop
-    Score::new(4, 6), // Rook
-    Score::new(-1, 12), // Queen
-    Score::new(-11, 13), // King
-];
-pub const WINDOW: [i32; 3] = [25, 100, 400];
-// A simple pawn transposition table using a hash map.
-// Key: zobrist hash of pawn structure, Value: evaluation score (i32)
-pub struct SearchInfo {
-    pub killer_moves: [[util::Move; 2]; 64], // Two killer moves per depth
-    pub history: [[i16; 64]; 64], // history heuristic
-    pub nodes: u64,
-}
-
-impl SearchInfo {
-    const HISTORY_MAX: i32 = 16384;
-
-    pub fn new() -> Self {
-        Self {
-            killer_moves: [[util::Move::from_parts(
-            0 as u8,
-            0 as u8,
-            util::MoveFlag::Quiet as u8,
-            ); 2]; 64], // Max depth 64
-            history: [[0; 64]; 64],
-            nodes: 0,
-        }
-    }
-    
-    pub fn update_killer(&mut self, depth: usize, mv: util::Move) {
-        if self.killer_moves[depth][0] != mv {
-            self.killer_moves[depth][1] = self.killer_moves[depth][0];
-            self.killer_moves[depth][0] = mv;
-        }
-    }
-    pub fn update_history(&mut self, from: usize, to: usize, depth_remaining: u16, qs: &[(usize, usize)]) {
-        let bonus = (depth_remaining as i32) * (depth_remaining as i32);
-        self.apply_history_delta(from, to, bonus);
-        for &(q_from, q_to) in qs {
-            self.apply_history_delta(q_from, q_to, -bonus);
-        }
-    }
-    fn apply_history_delta(&mut self, from: usize, to: usize, bonus: i32) {
-        let current = self.history[from][to] as i32;
-        let delta = bonus - current * bonus.abs() / Self::HISTORY_MAX;
-        let updated = (current + delta).clamp(-Self::HISTORY_MAX, Self::HISTORY_MAX);
-        self.history[from][to] = updated as i16;
-    }
-    pub fn next_move(&mut self) {
-        // Reset killer moves and history table for the next move
-        for i in 0..62 {
-            self.killer_moves[i] = self.killer_moves[i+2];
-        }
-        for i in 0..=63 {
-            for j in 0..=63 {
-                self.history[i][j] /= 2;
-            }
-        }
-        self.nodes = 0; // Reset node count for the next move
-    }
-}
+use std::sync::atomic::Ordering;
+use bytechess::{board, util, tuner, bench, suite};
+use bytechess::{SearchLimits, SearchState, SearchThread};
+use bytechess::table::{TranspositionTable, DEFAULT_PAWN_TT_SIZE_MB};
+use bytechess::timeman::TimeManager;
 fn main() {
     use std::io::{self, Write, BufRead};
     let args: Vec<String> = env::args().collect();
@@ -101,19 +15,39 @@ fn main() {
         tuner::main();
         return;
     }
+    if args.len() > 1 && args[1] == "bench" {
+        bench::run(args.get(2).and_then(|d| d.parse().ok()).unwrap_or(bench::DEFAULT_DEPTH));
+        return;
+    }
+    if args.len() > 1 && args[1] == "perft" {
+        // `bytechess perft N [bulk] [FEN]`, the start position when no FEN is given
+        let Some(depth) = args.get(2).and_then(|d| d.parse().ok()) else {
+            eprintln!("usage: bytechess perft <depth> [bulk] [FEN]");
+            std::process::exit(2);
+        };
+        let bulk = args.get(3).is_some_and(|a| a == "bulk");
+        let fen = args[if bulk { 4 } else { 3 }..].join(" ");
+        match board::Board::from_fen(if fen.is_empty() { board::STARTPOS_FEN } else { &fen }) {
+            Ok(mut board) => util::print_perft(&mut board, depth, bulk),
+            Err(e) => {
+                eprintln!("invalid FEN: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if args.len() > 1 && args[1] == "epd" {
+        if let Err(e) = suite::main(&args[2..]) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+        return;
+    }
     let stdin = io::stdin();
-    let mut board = util::board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-    board.zobrist_hash = zobrist::zobrist_hash(&board);
-    let mut search_info = SearchInfo::new();
+    let mut board = board::Board::startpos();
     let mut hash_size_mb = 256;
-    let mut tt = TranspositionTable::new(hash_size_mb);
-    let mut input_fen = String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-    let mut my_time: u64 = 1000 * 160;      // Bot's remaining time in ms
-    let mut my_inc: u64 = 1000 * 0;       // Bot's increment in ms, keep at 0 if updating from uci
-    let mut opp_time: u64 = 0;     // Opponent's remaining time in ms
-    let mut opp_inc: u64 = 0;      // Opponent's increment in ms
-    let mut mate_eval = 99900; // Evaluation to find checkmates, can be adjusted
-    let mut pawn_tt = table::PawnTable::new(); // Initialize pawn transposition table
+    let mut input_fen = String::from(board::STARTPOS_FEN);
+    let mut search = SearchThread::new(SearchState::new(hash_size_mb));
 
     println!("id name ByteChess");
     println!("id author Harrison Mesh");
@@ -129,45 +63,93 @@ fn main() {
                 println!("id name ByteChess");
                 println!("id author Harrison Mesh");
                 println!("option name Hash type spin default 256 min 1 max 1024");
+                println!("option name Clear Hash type button");
+                println!("option name PawnHash type spin default {} min 1 max 1024", DEFAULT_PAWN_TT_SIZE_MB);
+                println!("option name MultiPV type spin default 1 min 1 max 218");
+                println!("option name Move Overhead type spin default {} min 0 max 5000", TimeManager::DEFAULT_OVERHEAD);
+                println!("option name Ponder type check default false");
+                println!("option name Threads type spin default 1 min 1 max 256");
+                println!("option name UCI_Chess960 type check default false");
                 println!("uciok");
             }
             "isready" => {
                 println!("readyok");
             }
             "setoption" => {
-                if tokens.len() >= 5 && tokens[1] == "name" && tokens[2] == "Hash" && tokens[3] == "value" {
-                    if let Ok(value) = tokens[4].parse::<usize>() {
-                        hash_size_mb = value;
-                        tt = TranspositionTable::new(hash_size_mb);
+                // setoption name <id> [value <x>], where both the name and value may contain spaces
+                let value_idx = tokens.iter().position(|&t| t == "value").unwrap_or(tokens.len());
+                let name = tokens[2.min(value_idx)..value_idx].join(" ");
+                let value = tokens[(value_idx + 1).min(tokens.len())..].join(" ");
+                match name.as_str() {
+                    "Hash" => {
+                        if let Ok(value) = value.parse::<usize>() {
+                            hash_size_mb = value;
+                            search.state().tt = TranspositionTable::new(hash_size_mb);
+                        }
+                    }
+                    "Clear Hash" => {
+                        search.state().clear();
+                    }
+                    "PawnHash" => {
+                        if let Ok(value) = value.parse::<usize>() {
+                            search.state().set_pawn_hash(value.clamp(1, 1024));
+                        }
+                    }
+                    "UCI_Chess960" => {
+                        util::CHESS960.store(value == "true", Ordering::Relaxed);
+                    }
+                    "Threads" => {
+                        if let Ok(value) = value.parse::<usize>() {
+                            search.state().set_threads(value.clamp(1, 256));
+                        }
+                    }
+                    "Move Overhead" => {
+                        if let Ok(value) = value.parse::<u64>() {
+                            search.state().move_overhead = value.min(5000);
+                        }
+                    }
+                    "MultiPV" => {
+                        if let Ok(value) = value.parse::<usize>() {
+                            search.state().multi_pv = value.clamp(1, 218);
+                        }
                     }
+                    _ => {}
                 }
             }
+            "bench" => {
+                search.stop();
+                bench::run(tokens.get(1).and_then(|d| d.parse().ok()).unwrap_or(bench::DEFAULT_DEPTH));
+            }
             "testeval" => {
                 // Print the evaluation of the current position
                 util::print_eval(&board);
             }
             "ucinewgame" => {
-                mate_eval = 99900; // Reset mate evaluation for new game
-                board = util::board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-                board.zobrist_hash = zobrist::zobrist_hash(&board);
-                tt = TranspositionTable::new(hash_size_mb);
-                input_fen = String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+                let state = search.state();
+                board = board::Board::startpos();
+                state.clear();
+                input_fen = String::from(board::STARTPOS_FEN);
                 let mut board_hist: Vec<String> = Vec::new();
                 board_hist.push(input_fen.clone());
-                search_info = SearchInfo::new();
             }
             "position" => {
                 let mut idx = 1;
                 if tokens.len() > 1 && tokens[1] == "startpos" {
-                    board = util::board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-                    board.zobrist_hash = zobrist::zobrist_hash(&board);
-                    input_fen = String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+                    board = board::Board::startpos();
+                    input_fen = String::from(board::STARTPOS_FEN);
                     idx += 1;
                 } else if tokens.len() > 2 && tokens[1] == "fen" {
                     let moves_idx = tokens.iter().position(|&s| s == "moves").unwrap_or(tokens.len());
-                    input_fen = tokens[2..moves_idx].join(" ");
-                    board = util::board_from_fen(&input_fen);
-                    board.zobrist_hash = zobrist::zobrist_hash(&board);
+                    let fen = tokens[2..moves_idx].join(" ");
+                    match board::Board::from_fen(&fen) {
+                        Ok(new_board) => board = new_board,
+                        Err(e) => {
+                            // Keep the previous position, the moves belong to the rejected one
+                            println!("info string invalid FEN: {}", e);
+                            continue;
+                        }
+                    }
+                    input_fen = fen;
                     // Split the FEN string to count its parts (should be 6 for a full FEN)
                     let fen_parts: Vec<&str> = input_fen.split_whitespace().collect();
                     idx = 2 + fen_parts.len();
@@ -184,424 +166,28 @@ fn main() {
                     }
                 }
             }
-            "go" => {           
-                // Parse time controls from the command
-                let mut i = 1;
-                while i < tokens.len() {
-                    match tokens[i] {
-                        "wtime" => {
-                            if board.move_color == util::Color::White as i8 {
-                                my_time = tokens[i + 1].parse().unwrap_or(0);
-                            } else {
-                                opp_time = tokens[i + 1].parse().unwrap_or(0);
-                            }
-                            i += 2;
-                        }
-                        "btime" => {
-                            if board.move_color == util::Color::Black as i8 {
-                                my_time = tokens[i + 1].parse().unwrap_or(0);
-                            } else {
-                                opp_time = tokens[i + 1].parse().unwrap_or(0);
-                            }
-                            i += 2;
-                        }
-                        "winc" => {
-                            if board.move_color == util::Color::White as i8 {
-                                my_inc = tokens[i + 1].parse().unwrap_or(0);
-                            } else {
-                                opp_inc = tokens[i + 1].parse().unwrap_or(0);
-                            }
-                            i += 2;
-                        }
-                        "binc" => {
-                            if board.move_color == util::Color::Black as i8 {
-                                my_inc = tokens[i + 1].parse().unwrap_or(0);
-                            } else {
-                                opp_inc = tokens[i + 1].parse().unwrap_or(0);
-                            }
-                            i += 2;
-                        }
-                        _ => { i += 1; }
-                    }
-                }
-
-                // Record the start time before move calculation
-                let start = std::time::Instant::now();
-                let think_time = my_time/20 + my_inc/2; // 5% of time + half increment for thinking time
-                let m = think(&mut board, think_time, start, &mut tt, &mut mate_eval, &mut search_info, &mut pawn_tt);
-                // After move selection, update the bot's time
-                let elapsed = start.elapsed().as_millis() as u64;
-                my_time = my_time.saturating_sub(elapsed).saturating_add(my_inc);
-                // Play the first legal move (pl with legality check)
-                println!("bestmove {}", m);
-            }
-            "quit" | "exit" => {
-                break;
-            }
-            _ => {}
-        }
-        io::stdout().flush().unwrap();
-    }
-}
-fn think(board: &mut board::Board, think_time: u64, timer: std::time::Instant, tt: &mut TranspositionTable, mate_eval: &mut i32, search_info: &mut SearchInfo, pawn_tt: &mut PawnTable) -> util::Move {
-    // Thinking logic
-    tt.next_age();
-    search_info.next_move();
-    let mut depth = 0;    
-    let mut moves = board.get_ordered_moves(false,true, false, None, &search_info.killer_moves[0], &search_info.history);
-    let inf: i32 = i32::MIN + 1;
-    let mut alpha = inf;
-    let mut best_move = moves.first().clone(); // Save the first (ordered) legal move as a placeholder
-    if moves.len() == 1 { // If there's only one possible move, return it immediately
-        return best_move;
-    }
-    let eg = board.is_pawn_endgame();
-    let mut previous_best_move = best_move.clone();
-    let mut prev_eval = 0;
-    let mut pv = Vec::new();
-    if let Some(entry) = tt.probe(board.zobrist_hash) {
-        if let Some(mv) = entry.get_best_move() {
-            best_move = mv;
-            previous_best_move = best_move;
-        }
-    }
-    while timer.elapsed().as_millis() < think_time as u128 {
-        moves = board.get_ordered_moves(false,true, false, Some(previous_best_move), &search_info.killer_moves[0], &search_info.history);
-        let mut root_info: (Move, i32, Vec<Move>) = (best_move, 0, vec![best_move]);
-        let mut exact_eval = false;
-        let mut i = 0;
-        let mut j = 0;
-        while (!exact_eval && i < 4 && j < 4 && timer.elapsed().as_millis() < think_time as u128) {
-            let low  = if i > 2 { alpha } else { prev_eval - WINDOW[i] };
-            let high = if j > 2 { -alpha } else { prev_eval + WINDOW[j] };
-            root_info = think_eval(board, think_time, timer, tt, mate_eval, search_info, eg, pawn_tt, low, high, depth, moves);
-            if root_info.1 <= low {
-                i += 1;
-            }
-            else if root_info.1 >= high {
-                j += 1;
+            "go" if tokens.get(1) == Some(&"perft") => {
+                // `go perft N [bulk]`, the divide of the current position
+                search.stop();
+                let depth = tokens.get(2).and_then(|d| d.parse().ok()).unwrap_or(1);
+                util::print_perft(&mut board, depth, tokens.contains(&"bulk"));
             }
-            else {
-                exact_eval = true;
-            }
-        }
-        pv = root_info.2;
-        best_move = root_info.0;
-        alpha = root_info.1;
-        let pv_string = pv.iter().map(|mv| format!("{}", mv)).collect::<Vec<_>>().join(" ");
-        let elapsed = timer.elapsed().as_millis(); 
-        if elapsed >= think_time as u128 {
-            alpha = prev_eval
-        }
-        println!(
-            "info score cp {} depth {} nodes {} time {} pv {} move {}",
-            alpha, depth, search_info.nodes, elapsed, pv_string, best_move
-        );
-        if alpha >= *mate_eval || alpha <= -*mate_eval {
-                // If the evaluation is a checkmate, return the move we found
-                *mate_eval = alpha + 2; // Make sure to raise the mate threshold so we only return a faster checkmate next time
-                return best_move;
-        }
-        previous_best_move = best_move.clone();
-        prev_eval = alpha;
-        alpha = i32::MIN + 1;
-        depth += 1;
-        if depth > 64 {
-            // Limit the search depth to prevent excessive computation
-            // Mainly used to prevent crashes in positions that are "dead" draws without accidentally blundering mate
-            return best_move;
-        }
-    }
-    best_move
-}
-fn think_eval(board: &mut board::Board, think_time: u64, timer: std::time::Instant, tt: &mut TranspositionTable, mate_eval: &mut i32, search_info: &mut SearchInfo, eg: bool, pawn_tt: &mut PawnTable, a: i32, beta: i32, depth: i32, moves: MoveStack) -> (util::Move, i32, Vec<util::Move>) {
-    let mut best_move = moves.first().clone();
-    let mut local_pv = Vec::new();
-    let mut alpha  = a;
-    for (idx, m) in moves.iter().enumerate()
-        {
-            board::make_move(board,&m);
-            let mut child_pv = Vec::new();
-            let mut i = 0;
-            let mut j = 0;
-            let mut eval;
-            if idx == 0 {
-                // alpha not set, full window search
-                eval = -minimax(board, depth, 0, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+            "go" => {
+                let limits = SearchLimits::from_go(&tokens, &mut board);
+                search.go(board.clone(), limits); // bestmove is printed by the search thread
             }
-            else if depth >= 3 && idx >= 2 {
-                eval = -minimax(board, depth-1, 0, -alpha-1, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
-                if eval > alpha && eval < beta && timer.elapsed().as_millis() < think_time as u128 { // still beats it, do full window
-                    eval = -minimax(board, depth,  0, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
-                }
+            "stop" => {
+                search.stop();
             }
-            else {
-                eval = -minimax(board, depth, 0, -alpha-1, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
-                if eval > alpha && eval < beta && timer.elapsed().as_millis() < think_time as u128 { // still beats it, do full window
-                    eval = -minimax(board, depth,  0, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
-                }
+            "ponderhit" => {
+                search.ponderhit();
             }
-            
-            if timer.elapsed().as_millis() > think_time as u128 {
-                board::undo_move(board);
+            "quit" | "exit" => {
                 break;
             }
-            if eval > alpha {
-                alpha = eval;
-                best_move = m.clone();
-                local_pv.clear();
-                local_pv.push(m.clone());
-                local_pv.extend(child_pv)
-            }
-            board::undo_move(board);
-        }
-        (best_move, alpha, local_pv)
-}
-#[inline]
-fn is_quiet_move(mv: util::Move) -> bool {
-    let flags = mv.flags();
-    (flags & util::MoveFlag::Capture as u8) == 0 && (flags & 8) == 0
-}
-fn minimax(board: &mut board::Board, depth: i32, depth_searched: i32, mut alpha: i32, beta: i32, think_time: u64, timer: std::time::Instant, tt: &mut TranspositionTable, pv: &mut Vec<util::Move>, search_info: &mut SearchInfo, eg: bool, pawn_tt: &mut PawnTable) -> i32 {
-    search_info.nodes += 1;
-    let r = 3; // Reduction factor
-    if board.is_draw() {
-        pv.clear();
-        return 0; // Draw by repetition or 50 move or drawn endgame; checked before hash to avoid draws on decreasing depth!
-    }
-    // TT probe
-    let mut tt_best_move = None;
-    if let Some(entry) = tt.probe(board.zobrist_hash) {
-        if entry.get_depth() >= depth {
-            match entry.get_bound() {
-                Bound::Exact => {   
-                    pv.clear();
-                    if let Some(mv) = entry.get_best_move() {
-                        pv.push(mv);
-                    }
-                    return entry.score;}
-                Bound::Lower => if entry.score >= beta { return entry.score; },
-                Bound::Upper => if entry.score <= alpha { return entry.score; },
-            }
-        }
-        tt_best_move = entry.get_best_move();
-    }
-    let killer_moves = if (depth_searched as usize) < search_info.killer_moves.len() {
-        search_info.killer_moves[depth_searched as usize]
-    } else {
-        [util::Move::from_parts(
-        0 as u8,
-        0 as u8,
-        util::MoveFlag::Quiet as u8,
-        ); 2]};
-    if depth == 0 {
-            pv.clear();
-            return minimax_captures(board, depth_searched, alpha, beta, depth_searched, search_info, pawn_tt);
-    }
-    let is_check = board::is_check(board);
-    if !eg && depth >= r && !is_check { //null move conditions met
-        // Perform null move pruning
-        board::make_null_move(board);
-        let mut null_pv = Vec::new();
-        let eval = -minimax(board, depth - r, depth_searched + 1, -beta, -alpha, think_time, timer, tt, &mut null_pv, search_info, eg, pawn_tt);
-        board::undo_null_move(board);
-        if eval >= beta {
-            tt.store(TTEntry {
-                zobrist: board.zobrist_hash,
-                best_move: 0,
-                depth: depth as u8,
-                score: beta,
-                bound: Bound::Lower.to_u8(),
-                age: tt.age,
-                _pad: 0,
-            });
-            pv.clear();
-            return beta; // Beta cut-off
-        }
-        if eval > alpha {
-            alpha = eval; // Update alpha
-        }
-    }
-    let mut has_moves = false;
-    let mut best_score = i32::MIN + 1;
-    let mut best_move: Option<util::Move> = None;
-    let mut best_pv: Vec<util::Move> = Vec::new();
-    let mut searched_hash_move = false;
-    let mut moves_searched: u32 = 0;
-    // If a hash move exists, try it first
-    if let Some(hash_move) = tt_best_move {
-        let m = hash_move;
-        board::make_move(board, &m);
-        searched_hash_move = true;
-        moves_searched += 1;
-        has_moves = true;
-        let mut child_pv = Vec::new();
-        let mut eval;
-        // late move reduction not applied to hash move
-        eval = -minimax(board, depth - 1, depth_searched + 1, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
-        if (search_info.nodes & 0x3FF) == 0 && timer.elapsed().as_millis() > think_time as u128 {
-            board::undo_move(board);
-            pv.clear();
-            pv.extend(best_pv.iter());
-            return alpha;
-        }
-        if eval >= beta {
-            board::undo_move(board);
-            if is_quiet_move(m) {
-                search_info.update_killer(depth_searched as usize, m);
-                search_info.update_history(m.from_square() as usize, m.to_square() as usize, depth.max(1) as u16, &[]);
-            }
-            pv.clear();
-            pv.push(m);
-            pv.extend(child_pv);
-            tt.store(TTEntry {
-                zobrist: board.zobrist_hash,
-                best_move: m.info,
-                depth: depth as u8,
-                score: beta,
-                bound: Bound::Lower.to_u8(),
-                age: tt.age,
-                _pad: 0,
-            });
-            return beta;
-        }
-        if eval > alpha {
-            alpha = eval;
-            best_score = eval;
-            best_move = Some(m);
-            best_pv.clear();
-            best_pv.push(m);
-            best_pv.extend(child_pv);
-        } 
-        board::undo_move(board);
-    }
-    let mut moves = board.get_ordered_moves(false, false, false, tt_best_move, &killer_moves, &search_info.history);
-    let mut quiet_searched = Vec::new();
-    for (m_index, m) in moves.iter().enumerate(){
-        if searched_hash_move {
-            if let Some(hash_move) = tt_best_move {
-                if *m == hash_move {
-                    continue; // Skip the hash move if it was already tried
-                }
-            }
-        }
-        board::make_move(board, &m);
-        if !board.king_is_attacked()
-        {
-            has_moves = true;
-            let mut child_pv = Vec::new();
-            let mut eval;
-            if moves_searched == 0 {
-                // Normal search
-                eval = -minimax(board, depth - 1, depth_searched + 1, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
-            }
-            // Late move reduction
-            else if depth >= 3 && moves_searched > 1
-            {
-                // Reduce the depth for later moves
-                // Zero Window Search (PVS)
-                eval = -minimax(board, depth - 2, depth_searched + 1, -alpha-1, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
-                if eval > alpha && eval < beta { // still beats it, do full window
-                    eval = -minimax(board, depth - 1, depth_searched + 1, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
-                }
-
-            } else { // Just PVS
-                eval = -minimax(board, depth - 1, depth_searched + 1, -alpha-1, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
-                if eval > alpha && eval < beta { //beats it, do full window
-                    eval = -minimax(board, depth - 1, depth_searched + 1, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
-                }            
-            }
-            if (search_info.nodes & 0x3FF) == 0 && timer.elapsed().as_millis() > think_time as u128 {
-                board::undo_move(board);
-                pv.clear();
-                pv.extend(best_pv.iter());
-                return alpha
-            }
-            if eval >= beta {
-                board::undo_move(board);
-                if is_quiet_move(*m) {
-                    search_info.update_killer(depth_searched as usize, *m);
-                    search_info.update_history(m.from_square() as usize, m.to_square() as usize, depth.max(1) as u16, &quiet_searched);
-                }
-                pv.clear();
-                pv.push(*m);
-                pv.extend(child_pv);
-                tt.store(TTEntry {
-                    zobrist: board.zobrist_hash,
-                    best_move: m.info,
-                    depth: depth as u8,
-                    score: beta,
-                    bound: Bound::Lower.to_u8(),
-                    age: tt.age,
-                    _pad: 0,
-                });
-                return beta; // Beta cut-off
-            }
-            if eval > alpha {
-                alpha = eval; // Update alpha
-                best_score = eval;
-                best_move = Some(*m);
-                best_pv.clear();
-                best_pv.push(*m);
-                best_pv.extend(child_pv);
-            }
-            if is_quiet_move(*m) {
-                quiet_searched.push((m.from_square() as usize, m.to_square() as usize));
-            }
-            moves_searched += 1;
-        }
-        board::undo_move(board);
-    }
-    if !has_moves {
-        pv.clear();
-        if is_check {
-            return (depth_searched - 100000); // Checkmate
-        } else {
-            return 0; // Stalemate
-        }
-    }
-    // Store TT entry (exact or upper bound)
-    tt.store(TTEntry {
-        zobrist: board.zobrist_hash,
-        best_move: best_move.map(|m| m.info).unwrap_or(0),
-        depth: depth as u8,
-        score: alpha,
-        bound: if best_score > i32::MIN + 1 { Bound::Exact } else { Bound::Upper }.to_u8(),
-        age: tt.age,
-        _pad: 0,
-    });
-    pv.clear();
-    pv.extend(best_pv.iter());
-    alpha
-}
-// to_do -> include checks to make eval a truly quiet position
-fn minimax_captures(board: &mut board::Board, depth_searched: i32, mut alpha: i32, beta: i32, depth: i32, search_info: &mut SearchInfo, pawn_tt: &mut PawnTable) -> i32 {
-    search_info.nodes += 1;
-    let eval = util::evaluate(board, pawn_tt);
-    if eval >= beta {
-        return beta;
-    } else if eval >= alpha {
-        alpha = eval;
-    }
-    let mut moves = board.get_ordered_moves(false, false, true, None, &[util::Move::from_parts(
-        0 as u8,
-        0 as u8,
-        util::MoveFlag::Quiet as u8,
-        ); 2], &search_info.history);
-    if depth_searched <= 2 * depth && moves.len() != 0
-    {
-        for m in moves.iter(){
-            board::make_move(board, &m);
-            let eval = -minimax_captures(board, depth_searched + 1, -beta, -alpha, depth, search_info, pawn_tt);
-            if eval >= beta {
-                board::undo_move(board);
-                return beta; // Beta cut-off
-            }
-            if eval > alpha {
-                alpha = eval; // Update alpha
-            }
-            board::undo_move(board);
+            _ => {}
         }
+        io::stdout().flush().unwrap();
     }
-    alpha
+    search.stop();
 }
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use crate::board::Board;
+use crate::san::SanError;
+use crate::util::{FenError, Move};
+
+// Why an EPD record could not be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EpdError {
+    MissingFields(usize), // fewer than the 4 position fields
+    Fen(FenError),
+    UnterminatedString(String), // the opcode whose quoted operand never closes
+    BadOperand { opcode: String, operand: String }, // for opcodes with a fixed operand type, like `hmvc`
+}
+
+impl std::fmt::Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EpdError::MissingFields(n) => write!(f, "expected 4 position fields, got {}", n),
+            EpdError::Fen(e) => write!(f, "invalid position: {}", e),
+            EpdError::UnterminatedString(opcode) => write!(f, "unterminated string in '{}'", opcode),
+            EpdError::BadOperand { opcode, operand } => write!(f, "invalid operand '{}' for '{}'", operand, opcode),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+impl From<FenError> for EpdError {
+    fn from(e: FenError) -> Self {
+        EpdError::Fen(e)
+    }
+}
+
+// One EPD record, a position plus its operations. `hmvc` and `fmvn` set the board's clocks
+#[derive(Clone)]
+pub struct Epd {
+    pub board: Board,
+    pub operations: BTreeMap<String, Vec<String>>, // opcode to operands, `bm` -> ["Nf3", "e4"]
+}
+
+impl Epd {
+    pub fn parse(line: &str) -> Result<Epd, EpdError> {
+        let mut rest = line.trim();
+        let mut fields = Vec::new();
+        // The 4 position fields, then optionally the two FEN clocks that many tuning sets keep
+        while fields.len() < 6 && !rest.is_empty() {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if fields.len() >= 4 && !rest[..end].bytes().all(|b| b.is_ascii_digit()) {
+                break;
+            }
+            fields.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+        if fields.len() < 4 {
+            return Err(EpdError::MissingFields(fields.len()));
+        }
+        let mut operations = parse_operations(rest)?;
+
+        // Clocks given as FEN fields become the opcodes, so writing the record back keeps them
+        let mut fen = fields[..4].join(" ");
+        for (i, opcode, default) in [(4, "hmvc", "0"), (5, "fmvn", "1")] {
+            if let Some(&clock) = fields.get(i) {
+                operations.entry(opcode.to_string()).or_insert_with(|| vec![clock.to_string()]);
+            }
+            let value = operations.get(opcode).and_then(|ops| ops.first()).map_or(default, |v| v.as_str());
+            if value.parse::<u16>().is_err() {
+                return Err(EpdError::BadOperand { opcode: opcode.to_string(), operand: value.to_string() });
+            }
+            fen.push(' ');
+            fen.push_str(value);
+        }
+        Ok(Epd { board: Board::from_fen(&fen)?, operations })
+    }
+
+    // The operands of an opcode, None when the record does not have it
+    pub fn get(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.get(opcode).map(|ops| ops.as_slice())
+    }
+
+    // The first operand of an opcode, for single-valued ones like `id` or `ce`
+    pub fn get_first(&self, opcode: &str) -> Option<&str> {
+        self.get(opcode).and_then(|ops| ops.first()).map(|op| op.as_str())
+    }
+
+    pub fn set(&mut self, opcode: &str, operands: Vec<String>) {
+        self.operations.insert(opcode.to_string(), operands);
+    }
+
+    // The SAN operands of a move opcode (`bm`, `am`, `pm`, ...) as moves of this position
+    pub fn moves(&self, opcode: &str) -> Result<Vec<Move>, SanError> {
+        let mut board = self.board.clone();
+        self.get(opcode).unwrap_or(&[]).iter().map(|san| board.parse_san(san)).collect()
+    }
+}
+
+// Splits `bm Nf3 e4; id "WAC.001"; c0 "a; b";` into opcodes and operands
+fn parse_operations(text: &str) -> Result<BTreeMap<String, Vec<String>>, EpdError> {
+    let mut operations = BTreeMap::new();
+    let mut chars = text.chars().peekable();
+    let mut opcode: Option<String> = None;
+    let mut operands: Vec<String> = Vec::new();
+    let mut word = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') if chars.peek() == Some(&'"') => string.push(chars.next().unwrap()),
+                        Some(c) => string.push(c),
+                        None => return Err(EpdError::UnterminatedString(opcode.unwrap_or_default())),
+                    }
+                }
+                operands.push(string);
+            }
+            ';' | ' ' | '\t' => {
+                if !word.is_empty() {
+                    if opcode.is_none() {
+                        opcode = Some(std::mem::take(&mut word));
+                    } else {
+                        operands.push(std::mem::take(&mut word));
+                    }
+                }
+                if c == ';' && let Some(op) = opcode.take() {
+                    operations.insert(op, std::mem::take(&mut operands));
+                }
+            }
+            _ => word.push(c),
+        }
+    }
+    // The last operation may miss its `;`
+    if !word.is_empty() {
+        if opcode.is_none() { opcode = Some(word); } else { operands.push(word); }
+    }
+    if let Some(op) = opcode {
+        operations.insert(op, operands);
+    }
+    Ok(operations)
+}
+
+// Reads every record of an EPD file with its line number, counted from 1, skipping blank lines and `#` comments
+pub fn read_epds(text: &str) -> Vec<(usize, Result<Epd, EpdError>)> {
+    text.lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| (i + 1, Epd::parse(line)))
+        .collect()
+}
+
+fn needs_quotes(opcode: &str, operand: &str) -> bool {
+    // Names and comments are strings by convention, everything else only when it has to be
+    opcode == "id" || (opcode.len() == 2 && opcode.starts_with('c') && opcode.as_bytes()[1].is_ascii_digit())
+        || operand.is_empty() || operand.contains([' ', '\t', ';', '"'])
+}
+
+// Writes the record back out, position fields first and then the operations in opcode order
+impl std::fmt::Display for Epd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fen = self.board.to_string();
+        let position: Vec<&str> = fen.split_whitespace().take(4).collect();
+        write!(f, "{}", position.join(" "))?;
+        for (opcode, operands) in &self.operations {
+            write!(f, " {}", opcode)?;
+            for operand in operands {
+                if needs_quotes(opcode, operand) {
+                    write!(f, " \"{}\"", operand.replace('"', "\\\""))?;
+                } else {
+                    write!(f, " {}", operand)?;
+                }
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
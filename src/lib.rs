@@ -0,0 +1,909 @@
+#![allow(unused)]
+use core::hash;
+use std::cmp::max;
+use std::env;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use crate::magic::ROOK_MAGICS;
+use crate::table::{PawnTable, DEFAULT_PAWN_TT_SIZE_MB};
+use crate::table::{TranspositionTable, TTEntry, Bound};
+use crate::timeman::TimeManager;
+use crate::movepick::MovePicker;
+use crate::util::Move;
+use util::{Score, MoveStack};
+pub mod board;
+pub mod util;
+pub mod magic;
+pub mod zobrist;
+pub mod table;
+pub mod tuner;
+pub mod tunereval;
+pub mod timeman;
+pub mod san;
+pub mod pgn;
+pub mod epd;
+pub mod suite;
+pub mod bench;
+pub mod movepick;
+pub const PIECE_VALUES: [Score; 8] = [
+    Score::new(0,0), // Empty
+    Score::new(0,0), // None
+    Score::new(73, 109), // Pawn
+    Score::new(306, 314), // Knight
+    Score::new(362, 325), // Bishop
+    Score::new(457, 625), // Rook
+    Score::new(1100, 1010), // Queen
+    Score::new(100000, 100000) // King
+];// === Mobility Weights ===
+pub const MOBILITY_VALUES: [Score; 8] = [
+    Score::new(0,0), Score::new(0,0), Score::new(0,0),
+    Score::new(9, 11), // Knight
+    Score::new(4, 10), // Bishop
+    Score::new(4, 6), // Rook
+    Score::new(-1, 12), // Queen
+    Score::new(-11, 13), // King
+];
+pub const WINDOW: [i32; 3] = [25, 100, 400];
+pub const MATE_SCORE: i32 = 100000; // being mated at the root, mates further away score closer to zero by one per ply
+pub const MATE_THRESHOLD: i32 = MATE_SCORE - 1000; // anything beyond this is a forced mate
+pub const MAX_DEPTH: i32 = 64;
+// Singular extensions: a TT move searched at least this deep, that beats every other move by the margin per ply
+// in a reduced search without it, is extended by a ply, and by two when it beats them by the double margin as well
+const SINGULAR_DEPTH: i32 = 6;
+const SINGULAR_MARGIN: i32 = 2;
+const DOUBLE_EXTENSION_MARGIN: i32 = 50;
+const MAX_DOUBLE_EXTENSIONS: u32 = 2; // per line, so extensions cannot keep the search from reaching the horizon
+const QSEARCH_MAX_PLY: i32 = 8; // plies of quiescence past the horizon
+// Flags and counters shared by the UCI thread and every thread of one search
+#[derive(Default)]
+pub struct SearchSignals {
+    pub stop: AtomicBool, // raised on `stop`/`quit` or when a limit is reached
+    pub ponder: AtomicBool, // searching on the opponent's time, no time limit until `ponderhit`
+    pub ponderhit_ms: AtomicU64, // time of the `ponderhit` since `go`, the think time counts from here
+    pub helper_nodes: AtomicU64, // nodes searched by the helpers, for reporting
+    wake: Mutex<()>, // with `woken`, lets a finished search sleep until `stop` or `ponderhit`
+    woken: Condvar,
+}
+
+impl SearchSignals {
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.notify();
+    }
+    // Taking the lock orders the flag store before the waiter's check, so no wakeup is lost
+    fn notify(&self) {
+        let _guard = self.wake.lock().unwrap();
+        self.woken.notify_all();
+    }
+    // `go infinite` and `go ponder` must not end before the GUI sends `stop` or `ponderhit`
+    pub fn wait_for_stop(&self, infinite: bool) {
+        let mut guard = self.wake.lock().unwrap();
+        while (infinite || self.ponder.load(Ordering::Acquire)) && !self.stop.load(Ordering::Relaxed) {
+            guard = self.woken.wait(guard).unwrap();
+        }
+    }
+}
+// A simple pawn transposition table using a hash map.
+// Key: zobrist hash of pawn structure, Value: evaluation score (i32)
+pub struct SearchInfo {
+    pub killer_moves: [[util::Move; 2]; 64], // Two killer moves per depth
+    pub history: [[i16; 64]; 64], // history heuristic
+    pub nodes: u64,
+    pub node_limit: u64, // `go nodes`, u64::MAX when unlimited
+    pub signals: Arc<SearchSignals>, // shared with the UCI thread and the other search threads
+    pub thread_id: usize, // 0 for the main search thread, 1.. for Lazy SMP helpers
+    pub seldepth: i32, // deepest ply reached in the current iteration, quiescence included
+    pub quiet: bool, // no info lines, for searches run outside of UCI like the test-suite runner
+    excluded_moves: [Option<util::Move>; 64], // per ply, the TT move left out of a singular extension search
+    double_extensions: u32, // along the line being searched
+    published_nodes: u64,
+}
+
+impl SearchInfo {
+    const HISTORY_MAX: i32 = 16384;
+
+    pub fn new() -> Self {
+        Self {
+            killer_moves: [[util::Move::from_parts(
+            0 as u8,
+            0 as u8,
+            util::MoveFlag::Quiet as u8,
+            ); 2]; 64], // Max depth 64
+            history: [[0; 64]; 64],
+            nodes: 0,
+            node_limit: u64::MAX,
+            signals: Arc::new(SearchSignals::default()),
+            thread_id: 0,
+            seldepth: 0,
+            quiet: false,
+            excluded_moves: [None; 64],
+            double_extensions: 0,
+            published_nodes: 0,
+        }
+    }
+    pub fn helper(thread_id: usize) -> Self {
+        Self { thread_id, ..Self::new() }
+    }
+    // Adds a helper's nodes since the last call to the shared count
+    pub fn publish_nodes(&mut self) {
+        if self.thread_id != 0 {
+            self.signals.helper_nodes.fetch_add(self.nodes - self.published_nodes, Ordering::Relaxed);
+            self.published_nodes = self.nodes;
+        }
+    }
+    pub fn total_nodes(&self) -> u64 {
+        self.nodes + self.signals.helper_nodes.load(Ordering::Relaxed)
+    }
+    #[inline]
+    pub fn stopped(&self) -> bool {
+        self.signals.stop.load(Ordering::Relaxed)
+    }
+    #[inline]
+    pub fn pondering(&self) -> bool {
+        self.signals.ponder.load(Ordering::Acquire)
+    }
+    // Time in ms counted against our clock, which only starts at `ponderhit` when pondering
+    pub fn clock_ms(&self, timer: std::time::Instant) -> u64 {
+        if self.pondering() {
+            return 0;
+        }
+        (timer.elapsed().as_millis() as u64).saturating_sub(self.signals.ponderhit_ms.load(Ordering::Relaxed))
+    }
+    // Raises the stop flag once the hard time limit or node budget is used up, returns whether the search must stop
+    pub fn check_limits(&self, think_time: u64, timer: std::time::Instant) -> bool {
+        if self.nodes >= self.node_limit || self.clock_ms(timer) >= think_time {
+            self.signals.stop();
+        }
+        self.stopped()
+    }
+    
+    pub fn update_killer(&mut self, depth: usize, mv: util::Move) {
+        if self.killer_moves[depth][0] != mv {
+            self.killer_moves[depth][1] = self.killer_moves[depth][0];
+            self.killer_moves[depth][0] = mv;
+        }
+    }
+    pub fn update_history(&mut self, from: usize, to: usize, depth_remaining: u16, qs: &[(usize, usize)]) {
+        let bonus = (depth_remaining as i32) * (depth_remaining as i32);
+        self.apply_history_delta(from, to, bonus);
+        for &(q_from, q_to) in qs {
+            self.apply_history_delta(q_from, q_to, -bonus);
+        }
+    }
+    fn apply_history_delta(&mut self, from: usize, to: usize, bonus: i32) {
+        let current = self.history[from][to] as i32;
+        let delta = bonus - current * bonus.abs() / Self::HISTORY_MAX;
+        let updated = (current + delta).clamp(-Self::HISTORY_MAX, Self::HISTORY_MAX);
+        self.history[from][to] = updated as i16;
+    }
+    pub fn next_move(&mut self) {
+        // Reset killer moves and history table for the next move
+        for i in 0..62 {
+            self.killer_moves[i] = self.killer_moves[i+2];
+        }
+        for i in 0..=63 {
+            for j in 0..=63 {
+                self.history[i][j] /= 2;
+            }
+        }
+        self.nodes = 0; // Reset node count for the next move
+        self.published_nodes = 0;
+    }
+}
+// Limits parsed from a UCI `go` command, clock values are already those of the side to move
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub time: Option<u64>, // remaining clock time in ms
+    pub inc: u64, // increment in ms
+    pub movestogo: Option<u64>,
+    pub movetime: Option<u64>,
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub mate: Option<i32>, // stop once a mate in this many moves is found
+    pub infinite: bool,
+    pub ponder: bool, // `go ponder`, the clock limits only apply after `ponderhit`
+    pub searchmoves: MoveStack, // `go searchmoves`, only these root moves are searched, all of them when empty
+    pub excluded: MoveStack, // root moves left out of the search, `go excludemoves` (not part of UCI)
+}
+
+impl SearchLimits {
+    const DEFAULT_TIME: u64 = 1000 * 160; // clock assumed when `go` comes without any limit
+
+    pub fn from_go(tokens: &[&str], board: &mut board::Board) -> Self {
+        let white_to_move = board.move_color == util::Color::White as i8;
+        let mut limits = Self::default();
+        let value = |i: usize| tokens.get(i + 1).and_then(|t| t.parse::<u64>().ok());
+        let mut i = 1;
+        while i < tokens.len() {
+            match tokens[i] {
+                "wtime" if white_to_move => { limits.time = value(i); i += 1; }
+                "btime" if !white_to_move => { limits.time = value(i); i += 1; }
+                "winc" if white_to_move => { limits.inc = value(i).unwrap_or(0); i += 1; }
+                "binc" if !white_to_move => { limits.inc = value(i).unwrap_or(0); i += 1; }
+                "wtime" | "btime" | "winc" | "binc" => { i += 1; } // opponent's clock
+                "movestogo" => { limits.movestogo = value(i); i += 1; }
+                "movetime" => { limits.movetime = value(i); i += 1; }
+                "depth" => { limits.depth = value(i).map(|d| d as i32); i += 1; }
+                "nodes" => { limits.nodes = value(i); i += 1; }
+                "mate" => { limits.mate = value(i).map(|m| m as i32); i += 1; }
+                "infinite" => { limits.infinite = true; }
+                "ponder" => { limits.ponder = true; }
+                "searchmoves" | "excludemoves" => {
+                    board.gen_moves(true, false);
+                    let legal_moves = board.moves;
+                    let list = if tokens[i] == "searchmoves" { &mut limits.searchmoves } else { &mut limits.excluded };
+                    // The move list runs until the first token that is not a legal move
+                    while let Some(mv) = tokens.get(i + 1).and_then(|t| legal_moves.iter().find(|m| format!("{}", m) == *t)) {
+                        list.push(*mv).ok();
+                        i += 1;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        if limits.time.is_none() && limits.movetime.is_none() && limits.depth.is_none()
+            && limits.nodes.is_none() && limits.mate.is_none() && !limits.infinite {
+            limits.time = Some(Self::DEFAULT_TIME);
+        }
+        limits
+    }
+    // Leaves a root search out of a move, for tools that want the best alternative to it
+    pub fn exclude(&mut self, mv: Move) {
+        self.excluded.push(mv).ok();
+    }
+    // Applies `searchmoves` and the excluded moves to the root moves, never leaving the root without a move
+    pub fn filter_root_moves(&self, moves: &mut MoveStack) {
+        let mut filtered = *moves;
+        if !self.searchmoves.is_empty() {
+            filtered.retain(|m| self.searchmoves.iter().any(|s| s == m));
+        }
+        filtered.retain(|m| !self.excluded.iter().any(|e| e == m));
+        if !filtered.is_empty() {
+            *moves = filtered;
+        }
+    }
+}
+// Number of moves to mate for a root score, negative when we are the side getting mated
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score >= MATE_THRESHOLD {
+        Some((MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_THRESHOLD {
+        Some(-(MATE_SCORE + score) / 2)
+    } else {
+        None
+    }
+}
+// The score part of an info line, `mate N` for forced mates and centipawns otherwise
+fn uci_score(score: i32) -> String {
+    match mate_in(score) {
+        Some(moves) => format!("mate {}", moves),
+        None => format!("cp {}", score),
+    }
+}
+// Everything the search keeps between moves; owned by the search thread while it is thinking
+pub struct SearchState {
+    pub tt: TranspositionTable,
+    pub search_info: SearchInfo,
+    pub pawn_tt: PawnTable,
+    pub multi_pv: usize, // number of root lines reported, the `MultiPV` option
+    pub helpers: Vec<(SearchInfo, PawnTable)>, // per-thread state of the Lazy SMP helpers, `Threads` - 1 of them
+    pub move_overhead: u64, // ms kept back per move for GUI and network lag, the `Move Overhead` option
+    pub pawn_hash_mb: usize, // size of each thread's pawn table, the `PawnHash` option
+}
+
+impl SearchState {
+    pub fn new(hash_size_mb: usize) -> Self {
+        Self {
+            tt: TranspositionTable::new(hash_size_mb),
+            search_info: SearchInfo::new(),
+            pawn_tt: table::PawnTable::new(DEFAULT_PAWN_TT_SIZE_MB), // Initialize pawn transposition table
+            multi_pv: 1,
+            helpers: Vec::new(),
+            move_overhead: TimeManager::DEFAULT_OVERHEAD,
+            pawn_hash_mb: DEFAULT_PAWN_TT_SIZE_MB,
+        }
+    }
+    pub fn set_threads(&mut self, threads: usize) {
+        self.helpers.truncate(threads.max(1) - 1);
+        while self.helpers.len() + 1 < threads {
+            self.helpers.push((SearchInfo::helper(self.helpers.len() + 1), PawnTable::new(self.pawn_hash_mb)));
+        }
+    }
+    pub fn set_pawn_hash(&mut self, size_mb: usize) {
+        self.pawn_hash_mb = size_mb;
+        self.pawn_tt = PawnTable::new(size_mb);
+        for (_, pawn_tt) in self.helpers.iter_mut() {
+            *pawn_tt = PawnTable::new(size_mb);
+        }
+    }
+    // Forgets everything learned from earlier searches: hash tables, killers and history
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.pawn_tt.clear();
+        self.search_info = SearchInfo { quiet: self.search_info.quiet, ..SearchInfo::new() };
+        for (thread_id, (info, pawn_tt)) in self.helpers.iter_mut().enumerate() {
+            *info = SearchInfo::helper(thread_id + 1);
+            pawn_tt.clear();
+        }
+    }
+}
+// Runs `think` on a worker thread so the UCI loop can keep answering `isready` and `stop`
+pub struct SearchThread {
+    state: Option<SearchState>,
+    handle: Option<thread::JoinHandle<SearchState>>,
+    signals: Arc<SearchSignals>,
+    start: std::time::Instant, // when the current search was started
+}
+
+impl SearchThread {
+    const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+    pub fn new(state: SearchState) -> Self {
+        Self {
+            signals: state.search_info.signals.clone(),
+            state: Some(state),
+            handle: None,
+            start: std::time::Instant::now(),
+        }
+    }
+    // Signals a running search to stop and waits for it to print `bestmove`
+    pub fn stop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.signals.stop();
+            self.state = Some(handle.join().expect("search thread panicked"));
+        }
+    }
+    // The search state, stopping any search that is still running
+    pub fn state(&mut self) -> &mut SearchState {
+        self.stop();
+        self.state.as_mut().unwrap()
+    }
+    // The opponent played the expected move, so the ponder search goes on as a normal timed search
+    pub fn ponderhit(&mut self) {
+        if self.handle.is_some() {
+            self.signals.ponderhit_ms.store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+            self.signals.ponder.store(false, Ordering::Release); // publishes ponderhit_ms
+            self.signals.notify();
+        }
+    }
+    pub fn go(&mut self, mut board: board::Board, limits: SearchLimits) {
+        self.stop();
+        let mut state = self.state.take().unwrap();
+        self.signals = state.search_info.signals.clone();
+        self.signals.stop.store(false, Ordering::Relaxed);
+        self.signals.ponder.store(limits.ponder, Ordering::Relaxed);
+        self.signals.ponderhit_ms.store(0, Ordering::Relaxed);
+        // Start the clock before spawning so thread start-up counts against our time
+        let start = std::time::Instant::now();
+        self.start = start;
+        let handle = thread::Builder::new()
+            .name("search".to_string())
+            .stack_size(Self::STACK_SIZE)
+            .spawn(move || {
+                let result = think(&mut board, &limits, start, &mut state);
+                state.search_info.signals.wait_for_stop(limits.infinite);
+                match result.pv.get(1) {
+                    Some(ponder_move) if result.pv[0] == result.best_move => {
+                        println!("bestmove {} ponder {}", result.best_move, ponder_move);
+                    }
+                    _ => println!("bestmove {}", result.best_move),
+                }
+                std::io::stdout().flush().unwrap();
+                state
+            })
+            .expect("failed to spawn search thread");
+        self.handle = Some(handle);
+    }
+}
+// Outcome of one thread's iterative deepening
+pub struct RootResult {
+    pub best_move: Move,
+    pub score: i32,
+    pub depth: i32, // last fully completed iteration, -1 if none finished
+    pub pv: Vec<Move>,
+    pub iterations: Vec<(i32, Move, u64)>, // depth, best move and elapsed ms of each completed iteration
+}
+// The `info` line of one root line, `bound` is not exact when an aspiration window failed
+fn print_info_line(multipv: usize, depth: i32, score: i32, bound: Bound, pv: &[Move], search_info: &SearchInfo, tt: &TranspositionTable, timer: std::time::Instant) {
+    if search_info.quiet {
+        return;
+    }
+    let elapsed = timer.elapsed().as_millis() as u64;
+    let nodes = search_info.total_nodes();
+    let bound = match bound {
+        Bound::Exact => "",
+        Bound::Lower => " lowerbound",
+        Bound::Upper => " upperbound",
+    };
+    let pv_string = pv.iter().map(|mv| format!(" {}", mv)).collect::<String>();
+    let pv_string = if pv.is_empty() { String::new() } else { format!(" pv{}", pv_string) }; // a failed low root has no line
+    println!(
+        "info depth {} seldepth {} multipv {} score {}{} nodes {} nps {} hashfull {} time {}{}",
+        depth, search_info.seldepth, multipv, uci_score(score), bound, nodes, nodes * 1000 / elapsed.max(1), tt.hashfull(), elapsed, pv_string
+    );
+}
+pub fn think(board: &mut board::Board, limits: &SearchLimits, timer: std::time::Instant, state: &mut SearchState) -> RootResult {
+    // Thinking logic
+    let SearchState { tt, search_info, pawn_tt, multi_pv, helpers, move_overhead, .. } = state;
+    tt.next_age();
+    search_info.next_move();
+    search_info.signals.helper_nodes.store(0, Ordering::Relaxed);
+    let moves = board.get_ordered_moves(false,true, false, None, &search_info.killer_moves[0], &search_info.history);
+    if moves.is_empty() {
+        // Checkmate or stalemate, reported as `mate 0` or a draw with the null move `0000`
+        let score = if board::is_check(board) { -MATE_SCORE } else { 0 };
+        print_info_line(1, 0, score, Bound::Exact, &[], search_info, tt, timer);
+        return RootResult { best_move: Move::null(), score, depth: 0, pv: Vec::new(), iterations: Vec::new() };
+    }
+    if moves.len() == 1 { // If there's only one possible move, return it immediately
+        return RootResult { best_move: moves.first(), score: 0, depth: 0, pv: vec![moves.first()], iterations: Vec::new() };
+    }
+    let time = TimeManager::new(limits, *move_overhead);
+    search_info.node_limit = limits.nodes.unwrap_or(u64::MAX);
+    let tt: &TranspositionTable = tt;
+    // Lazy SMP: helpers run their own iterative deepening on the same root and only talk to the main thread through the TT
+    thread::scope(|scope| {
+        let handles: Vec<_> = helpers.iter_mut().map(|(helper_info, helper_pawn_tt)| {
+            helper_info.next_move();
+            helper_info.signals = search_info.signals.clone();
+            let mut helper_board = board.clone();
+            thread::Builder::new()
+                .stack_size(SearchThread::STACK_SIZE)
+                .spawn_scoped(scope, move || {
+                    iterate(&mut helper_board, limits, 1, time, timer, tt, helper_info, helper_pawn_tt)
+                })
+                .expect("failed to spawn helper thread")
+        }).collect();
+        let mut result = iterate(board, limits, *multi_pv, time, timer, tt, search_info, pawn_tt);
+        // `go infinite` and `go ponder` keep the helpers searching until the GUI sends `stop` or `ponderhit`
+        search_info.signals.wait_for_stop(limits.infinite);
+        search_info.signals.stop();
+        // Take a helper's move only if it got deeper than the main thread
+        for handle in handles {
+            let helper_result = handle.join().expect("helper thread panicked");
+            if helper_result.depth > result.depth {
+                if search_info.thread_id == 0 {
+                    print_info_line(1, helper_result.depth, helper_result.score, Bound::Exact, &helper_result.pv, search_info, tt, timer);
+                }
+                result = helper_result;
+            }
+        }
+        result
+    })
+}
+// Iterative deepening on one thread, the main thread (thread_id 0) also reports info lines
+fn iterate(board: &mut board::Board, limits: &SearchLimits, multi_pv: usize, mut time: TimeManager, timer: std::time::Instant, tt: &TranspositionTable, search_info: &mut SearchInfo, pawn_tt: &mut PawnTable) -> RootResult {
+    let think_time = time.hard_limit();
+    let max_depth = limits.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH);
+    let mut depth = (search_info.thread_id % 2) as i32; // odd helpers skip ahead a ply to spread the threads over depths
+    let mut completed_depth = -1;
+    let mut moves = board.get_ordered_moves(false,true, false, None, &search_info.killer_moves[0], &search_info.history);
+    limits.filter_root_moves(&mut moves);
+    let inf: i32 = i32::MIN + 1;
+    let mut alpha = inf;
+    let mut best_move = moves.first().clone(); // Save the first (ordered) legal move as a placeholder
+    let eg = board.is_pawn_endgame();
+    let mut previous_best_move = best_move.clone();
+    let mut prev_eval = 0;
+    let mut pv = Vec::new();
+    let mut prev_lines: Vec<(Move, i32, Vec<Move>)> = Vec::new(); // MultiPV lines of the last iteration, best first
+    let mut iterations = Vec::new();
+    if let Some(entry) = tt.probe(board.zobrist_hash, 0) {
+        if let Some(mv) = entry.get_best_move() && moves.iter().any(|m| *m == mv) {
+            best_move = mv;
+            previous_best_move = best_move;
+        }
+    }
+    while !search_info.check_limits(think_time, timer) {
+        moves = board.get_ordered_moves(false,true, false, Some(previous_best_move), &search_info.killer_moves[0], &search_info.history);
+        limits.filter_root_moves(&mut moves);
+        // Each MultiPV line is a separate root search over the moves not already taken by a better line
+        let mut lines: Vec<(Move, i32, Vec<Move>)> = Vec::new();
+        let mut unfinished = false; // stopped before the first line of this iteration finished
+        search_info.seldepth = 0;
+        for pv_idx in 0..multi_pv.min(moves.len()).max(1) {
+            let mut line_moves = moves;
+            line_moves.retain(|m| !lines.iter().any(|line| line.0 == *m));
+            let mut line_eval = prev_eval;
+            if let Some(prev_line) = prev_lines.get(pv_idx) {
+                line_eval = prev_line.1;
+                let prev_idx = line_moves.iter().position(|m| *m == prev_line.0);
+                if let Some(idx) = prev_idx {
+                    line_moves.move_to_front(idx);
+                }
+            }
+            let mut root_info: (Move, i32, Vec<Move>) = (line_moves.first(), 0, vec![line_moves.first()]);
+            if pv_idx == 0 {
+                root_info = (best_move, 0, vec![best_move]);
+            }
+            let mut exact_eval = false;
+            let mut i = 0;
+            let mut j = 0;
+            while (!exact_eval && i < 4 && j < 4 && !search_info.check_limits(think_time, timer)) {
+                let low  = if i > 2 { alpha } else { line_eval - WINDOW[i] };
+                let high = if j > 2 { -alpha } else { line_eval + WINDOW[j] };
+                root_info = think_eval(board, think_time, timer, tt, search_info, eg, pawn_tt, low, high, depth, line_moves);
+                if root_info.1 <= low {
+                    i += 1;
+                }
+                else if root_info.1 >= high {
+                    j += 1;
+                }
+                else {
+                    exact_eval = true;
+                }
+                if !exact_eval && search_info.thread_id == 0 && !search_info.stopped() {
+                    let bound = if root_info.1 <= low { Bound::Upper } else { Bound::Lower };
+                    print_info_line(pv_idx + 1, depth, root_info.1, bound, &root_info.2, search_info, tt, timer);
+                }
+            }
+            if search_info.stopped() {
+                if pv_idx == 0 {
+                    root_info.1 = prev_eval;
+                    if root_info.0 == best_move && root_info.2.len() < pv.len() {
+                        root_info.2 = pv.clone(); // keep the full line of the last iteration, it has the ponder move
+                    }
+                    lines.push(root_info);
+                    unfinished = true;
+                }
+                break; // an unfinished line is dropped, the ones before it are complete
+            }
+            lines.push(root_info);
+        }
+        lines.sort_by_key(|line| std::cmp::Reverse(line.1));
+        pv = lines[0].2.clone();
+        best_move = lines[0].0;
+        alpha = lines[0].1;
+        if !search_info.stopped() {
+            completed_depth = depth;
+            time.update(best_move, alpha);
+            iterations.push((depth, best_move, timer.elapsed().as_millis() as u64));
+        }
+        // The previous iteration was already reported, nothing of this one is worth printing under its depth
+        if search_info.thread_id == 0 && !unfinished {
+            for (k, line) in lines.iter().enumerate() {
+                print_info_line(k + 1, depth, line.1, Bound::Exact, &line.2, search_info, tt, timer);
+            }
+        }
+        prev_lines = lines;
+        if let Some(mate) = limits.mate && mate_in(alpha).is_some_and(|n| n > 0 && n <= mate) {
+            break; // `go mate` is satisfied
+        }
+        // Only the main thread decides when to stop, the next iteration would likely not finish in time
+        if search_info.thread_id == 0 && time.should_stop(search_info.clock_ms(timer)) {
+            break;
+        }
+        previous_best_move = best_move.clone();
+        prev_eval = alpha;
+        alpha = i32::MIN + 1;
+        depth += 1;
+        if depth > max_depth {
+            // Limit the search depth to prevent excessive computation, or stop at the depth asked by `go depth`
+            // Mainly used to prevent crashes in positions that are "dead" draws without accidentally blundering mate
+            break;
+        }
+    }
+    search_info.publish_nodes();
+    RootResult { best_move, score: prev_lines.first().map_or(prev_eval, |line| line.1), depth: completed_depth, pv, iterations }
+}
+fn think_eval(board: &mut board::Board, think_time: u64, timer: std::time::Instant, tt: &TranspositionTable, search_info: &mut SearchInfo, eg: bool, pawn_tt: &mut PawnTable, a: i32, beta: i32, depth: i32, moves: MoveStack) -> (util::Move, i32, Vec<util::Move>) {
+    let mut best_move = moves.first().clone();
+    let mut local_pv = Vec::new();
+    let mut alpha  = a;
+    for (idx, m) in moves.iter().enumerate()
+        {
+            // Only worth the output once the search takes long enough for a GUI to show progress
+            if search_info.thread_id == 0 && !search_info.quiet && timer.elapsed().as_millis() > 1000 {
+                println!("info depth {} currmove {} currmovenumber {}", depth, m, idx + 1);
+            }
+            board::make_move(board,&m);
+            let mut child_pv = Vec::new();
+            let mut i = 0;
+            let mut j = 0;
+            let mut eval;
+            if idx == 0 {
+                // alpha not set, full window search
+                eval = -minimax(board, depth, 0, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+            }
+            else if depth >= 3 && idx >= 2 {
+                eval = -minimax(board, depth-1, 0, -alpha-1, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+                if eval > alpha && eval < beta && !search_info.stopped() { // still beats it, do full window
+                    eval = -minimax(board, depth,  0, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+                }
+            }
+            else {
+                eval = -minimax(board, depth, 0, -alpha-1, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+                if eval > alpha && eval < beta && !search_info.stopped() { // still beats it, do full window
+                    eval = -minimax(board, depth,  0, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+                }
+            }
+            
+            if search_info.stopped() {
+                board::undo_move(board);
+                break;
+            }
+            if eval > alpha {
+                alpha = eval;
+                best_move = m.clone();
+                local_pv.clear();
+                local_pv.push(m.clone());
+                local_pv.extend(child_pv)
+            }
+            board::undo_move(board);
+        }
+        (best_move, alpha, local_pv)
+}
+#[inline]
+fn is_quiet_move(mv: util::Move) -> bool {
+    let flags = mv.flags();
+    (flags & util::MoveFlag::Capture as u8) == 0 && (flags & 8) == 0
+}
+fn minimax(board: &mut board::Board, depth: i32, depth_searched: i32, mut alpha: i32, beta: i32, think_time: u64, timer: std::time::Instant, tt: &TranspositionTable, pv: &mut Vec<util::Move>, search_info: &mut SearchInfo, eg: bool, pawn_tt: &mut PawnTable) -> i32 {
+    search_info.nodes += 1;
+    search_info.seldepth = search_info.seldepth.max(depth_searched + 1);
+    if (search_info.nodes & 0x3FF) == 0 || search_info.nodes >= search_info.node_limit {
+        search_info.publish_nodes();
+        search_info.check_limits(think_time, timer);
+    }
+    let r = 3; // Reduction factor
+    if board.is_draw() {
+        pv.clear();
+        return 0; // Draw by repetition or 50 move or drawn endgame; checked before hash to avoid draws on decreasing depth!
+    }
+    // A singular extension search of this node, it is not the full node so the TT is neither used for cutoffs nor stored to
+    let excluded = search_info.excluded_moves.get(depth_searched as usize).copied().flatten();
+    // TT probe
+    let mut tt_best_move = None;
+    let mut tt_entry = None;
+    if let Some(entry) = tt.probe(board.zobrist_hash, depth_searched) {
+        if entry.get_depth() >= depth && excluded.is_none() {
+            match entry.get_bound() {
+                Bound::Exact => {   
+                    pv.clear();
+                    if let Some(mv) = entry.get_best_move() {
+                        pv.push(mv);
+                    }
+                    return entry.score;}
+                Bound::Lower => if entry.score >= beta { return entry.score; },
+                Bound::Upper => if entry.score <= alpha { return entry.score; },
+            }
+        }
+        tt_best_move = entry.get_best_move();
+        tt_entry = Some(entry);
+    }
+    let killer_moves = if (depth_searched as usize) < search_info.killer_moves.len() {
+        search_info.killer_moves[depth_searched as usize]
+    } else {
+        [util::Move::from_parts(
+        0 as u8,
+        0 as u8,
+        util::MoveFlag::Quiet as u8,
+        ); 2]};
+    if depth == 0 {
+            pv.clear();
+            return minimax_captures(board, depth_searched, alpha, beta, depth_searched, search_info, pawn_tt);
+    }
+    let is_check = board::is_check(board);
+    if !eg && depth >= r && !is_check && excluded.is_none() { //null move conditions met
+        // Perform null move pruning
+        board::make_null_move(board);
+        let mut null_pv = Vec::new();
+        let eval = -minimax(board, depth - r, depth_searched + 1, -beta, -alpha, think_time, timer, tt, &mut null_pv, search_info, eg, pawn_tt);
+        board::undo_null_move(board);
+        if eval >= beta {
+            tt.store(TTEntry {
+                zobrist: board.zobrist_hash,
+                best_move: 0,
+                depth: depth as u8,
+                score: beta,
+                bound: Bound::Lower.to_u8(),
+                age: tt.age(),
+                _pad: 0,
+            }, depth_searched);
+            pv.clear();
+            return beta; // Beta cut-off
+        }
+        if eval > alpha {
+            alpha = eval; // Update alpha
+        }
+    }
+    let mut has_moves = false;
+    let mut best_score = i32::MIN + 1;
+    let mut best_move: Option<util::Move> = None;
+    let mut best_pv: Vec<util::Move> = Vec::new();
+    let mut moves_searched: u32 = 0;
+    let mut picker = MovePicker::new(tt_best_move, killer_moves);
+    let mut quiet_searched = Vec::new();
+    // Whether the TT move is singular, the only move that holds the score it was stored with
+    let mut singular_extension = 0;
+    let singular_entry = tt_entry.filter(|entry| depth >= SINGULAR_DEPTH && excluded.is_none() && (depth_searched as usize) < search_info.excluded_moves.len()
+        && entry.get_bound() != Bound::Upper && entry.get_depth() >= depth - 3 && entry.score.abs() < MATE_THRESHOLD);
+    if let (Some(entry), Some(tt_move)) = (singular_entry, tt_best_move) {
+        let singular_beta = entry.score - SINGULAR_MARGIN * depth;
+        let singular_depth = (depth - 1) / 2;
+        search_info.excluded_moves[depth_searched as usize] = Some(tt_move);
+        // The search fails hard, so how far below the bound the other moves are takes a second probe at the lower one
+        let eval = minimax(board, singular_depth, depth_searched, singular_beta - 1, singular_beta, think_time, timer, tt, &mut Vec::new(), search_info, eg, pawn_tt);
+        if eval < singular_beta {
+            singular_extension = 1;
+            let double_beta = singular_beta - DOUBLE_EXTENSION_MARGIN;
+            if search_info.double_extensions < MAX_DOUBLE_EXTENSIONS
+                && minimax(board, singular_depth, depth_searched, double_beta - 1, double_beta, think_time, timer, tt, &mut Vec::new(), search_info, eg, pawn_tt) < double_beta {
+                singular_extension = 2;
+            }
+        }
+        search_info.excluded_moves[depth_searched as usize] = None;
+    }
+    while let Some(m) = picker.next(board, &search_info.history) {
+        if Some(m) == excluded {
+            continue;
+        }
+        // Checks are extended a ply so the forcing line is followed past the horizon, unless they just give material away
+        let extension = if Some(m) == tt_best_move && singular_extension > 0 {
+            singular_extension
+        } else if board.gives_check(&m) && board.see(&m) >= 0 {
+            1
+        } else {
+            0
+        };
+        let extension = if depth_searched + depth + extension < MAX_DEPTH { extension } else { 0 };
+        board::make_move(board, &m);
+        has_moves = true;
+        search_info.double_extensions += (extension == 2) as u32;
+        let mut child_pv = Vec::new();
+        let mut eval;
+        if moves_searched == 0 {
+            // Normal search
+            eval = -minimax(board, depth - 1 + extension, depth_searched + 1, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+        }
+        // Late move reduction
+        else if depth >= 3 && moves_searched > 1 && extension == 0
+        {
+            // Reduce the depth for later moves
+            // Zero Window Search (PVS)
+            eval = -minimax(board, depth - 2, depth_searched + 1, -alpha-1, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+            if eval > alpha && eval < beta { // still beats it, do full window
+                eval = -minimax(board, depth - 1, depth_searched + 1, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+            }
+
+        } else { // Just PVS
+            eval = -minimax(board, depth - 1 + extension, depth_searched + 1, -alpha-1, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+            if eval > alpha && eval < beta { //beats it, do full window
+                eval = -minimax(board, depth - 1 + extension, depth_searched + 1, -beta, -alpha, think_time, timer, tt, &mut child_pv, search_info, eg, pawn_tt);
+            }            
+        }
+        search_info.double_extensions -= (extension == 2) as u32;
+        if search_info.stopped() {
+            board::undo_move(board);
+            pv.clear();
+            pv.extend(best_pv.iter());
+            return alpha
+        }
+        if eval >= beta {
+            board::undo_move(board);
+            // A singular extension search is not the real node, its cutoffs would mislead the ordering there
+            if is_quiet_move(m) && excluded.is_none() {
+                search_info.update_killer(depth_searched as usize, m);
+                search_info.update_history(m.from_square() as usize, m.to_square() as usize, depth.max(1) as u16, &quiet_searched);
+            }
+            pv.clear();
+            pv.push(m);
+            pv.extend(child_pv);
+            if excluded.is_none() {
+                tt.store(TTEntry {
+                    zobrist: board.zobrist_hash,
+                    best_move: m.info,
+                    depth: depth as u8,
+                    score: beta,
+                    bound: Bound::Lower.to_u8(),
+                    age: tt.age(),
+                    _pad: 0,
+                }, depth_searched);
+            }
+            return beta; // Beta cut-off
+        }
+        if eval > alpha {
+            alpha = eval; // Update alpha
+            best_score = eval;
+            best_move = Some(m);
+            best_pv.clear();
+            best_pv.push(m);
+            best_pv.extend(child_pv);
+        }
+        if is_quiet_move(m) {
+            quiet_searched.push((m.from_square() as usize, m.to_square() as usize));
+        }
+        moves_searched += 1;
+        board::undo_move(board);
+    }
+    if !has_moves {
+        pv.clear();
+        if excluded.is_some() {
+            return alpha; // The TT move was the only move, so it is singular
+        } else if is_check {
+            return depth_searched + 1 - MATE_SCORE; // Checkmate, depth_searched + 1 plies from the root
+        } else {
+            return 0; // Stalemate
+        }
+    }
+    if excluded.is_some() {
+        return alpha;
+    }
+    // Store TT entry (exact or upper bound)
+    tt.store(TTEntry {
+        zobrist: board.zobrist_hash,
+        best_move: best_move.map(|m| m.info).unwrap_or(0),
+        depth: depth as u8,
+        score: alpha,
+        bound: if best_score > i32::MIN + 1 { Bound::Exact } else { Bound::Upper }.to_u8(),
+        age: tt.age(),
+        _pad: 0,
+    }, depth_searched);
+    pv.clear();
+    pv.extend(best_pv.iter());
+    alpha
+}
+// Quiescence search, captures until the position is quiet. In check there is no standing pat and every
+// evasion is searched, and on its first ply quiet moves that give check are tried too
+fn minimax_captures(board: &mut board::Board, depth_searched: i32, mut alpha: i32, beta: i32, entry_ply: i32, search_info: &mut SearchInfo, pawn_tt: &mut PawnTable) -> i32 {
+    search_info.nodes += 1;
+    search_info.seldepth = search_info.seldepth.max(depth_searched + 1);
+    let in_check = board::is_check(board);
+    let at_limit = depth_searched - entry_ply >= QSEARCH_MAX_PLY;
+    let mut moves = MoveStack::new();
+    if in_check {
+        moves = board.get_ordered_moves(false, true, false, None, &[util::Move::null(); 2], &search_info.history);
+        if moves.is_empty() {
+            return depth_searched + 1 - MATE_SCORE; // Checkmate, found at the horizon
+        }
+    }
+    // Past the limit a position in check is known not to be mate, so its static eval stands in for the evasions
+    if !in_check || at_limit {
+        let eval = util::evaluate(board, pawn_tt);
+        if eval >= beta {
+            return beta;
+        } else if eval >= alpha {
+            alpha = eval;
+        }
+        if at_limit {
+            return alpha;
+        }
+    }
+    if !in_check {
+        moves = board.get_ordered_moves(false, true, true, None, &[util::Move::null(); 2], &search_info.history);
+        if depth_searched == entry_ply {
+            board.gen_quiet_moves(true);
+            let quiets = board.moves;
+            for m in quiets.iter().filter(|m| board.gives_check(m)) {
+                let _ = moves.push(*m);
+            }
+        }
+    }
+    for m in moves.iter(){
+        // Captures that lose material on the exchange are left out, they rarely beat standing pat
+        if !in_check && m.flags() & util::MoveFlag::Capture as u8 != 0 && board.see(m) < 0 {
+            continue;
+        }
+        board::make_move(board, &m);
+        let eval = -minimax_captures(board, depth_searched + 1, -beta, -alpha, entry_ply, search_info, pawn_tt);
+        if search_info.stopped() {
+            board::undo_move(board);
+            return alpha;
+        }
+        if eval >= beta {
+            board::undo_move(board);
+            return beta; // Beta cut-off
+        }
+        if eval > alpha {
+            alpha = eval; // Update alpha
+        }
+        board::undo_move(board);
+    }
+    alpha
+}
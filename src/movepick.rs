@@ -0,0 +1,152 @@
+// Staged move ordering for the main search. Moves come out in the order
+// TT move, good captures, killers, quiet moves by history, bad captures, and every stage is
+// generated and scored only once the stages before it are used up, so a cutoff on the first
+// move or two skips most of the work
+use crate::board::Board;
+use crate::util::{Move, MoveStack};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Stage {
+    TtMove,
+    GenerateCaptures,
+    GoodCaptures,
+    GenerateQuiets,
+    Killers,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: Option<Move>,
+    killers: [Move; 2],
+    killer_index: usize,
+    moves: [Move; 218], // the stage being picked from, captures and then quiets
+    scores: [i32; 218],
+    len: usize,
+    index: usize, // moves before this one were already returned
+    bad_captures: MoveStack, // captures losing material, in the order they were met
+    bad_index: usize,
+}
+
+impl MovePicker {
+    // The TT move is trusted to be pseudo-legal here, the table checks the full key, and is checked for legality
+    pub fn new(tt_move: Option<Move>, killers: [Move; 2]) -> Self {
+        Self {
+            stage: Stage::TtMove,
+            tt_move,
+            killers,
+            killer_index: 0,
+            moves: [Move::null(); 218],
+            scores: [0; 218],
+            len: 0,
+            index: 0,
+            bad_captures: MoveStack::new(),
+            bad_index: 0,
+        }
+    }
+
+    // Swaps the best scored move left in the stage to `index` and returns it
+    fn pick_best(&mut self) -> Option<Move> {
+        if self.index >= self.len {
+            return None;
+        }
+        let mut best = self.index;
+        for i in self.index + 1..self.len {
+            if self.scores[i] > self.scores[best] {
+                best = i;
+            }
+        }
+        self.moves.swap(self.index, best);
+        self.scores.swap(self.index, best);
+        self.index += 1;
+        Some(self.moves[self.index - 1])
+    }
+
+    // The legal moves, each once
+    pub fn next(&mut self, board: &mut Board, history: &[[i16; 64]; 64]) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if let Some(m) = self.tt_move && board.is_legal_move(&m) {
+                        return Some(m);
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    board.gen_moves(true, true);
+                    self.len = 0;
+                    for m in board.moves.iter() {
+                        self.moves[self.len] = *m;
+                        self.scores[self.len] = m.score_move(m, None, &self.killers, board.attacking_piece(m), board.captured_piece(m), history);
+                        self.len += 1;
+                    }
+                    self.index = 0;
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    let Some(m) = self.pick_best() else {
+                        self.stage = Stage::GenerateQuiets;
+                        continue;
+                    };
+                    if Some(m) == self.tt_move {
+                        continue;
+                    }
+                    // Losing captures wait until the quiet moves are done
+                    if board.see(&m) < 0 {
+                        let _ = self.bad_captures.push(m);
+                        continue;
+                    }
+                    return Some(m);
+                }
+                Stage::GenerateQuiets => {
+                    board.gen_quiet_moves(true);
+                    self.len = 0;
+                    for m in board.moves.iter() {
+                        self.moves[self.len] = *m;
+                        // Killers are given no score of their own, they come out in their stage
+                        self.scores[self.len] = if m.flags() & 8 != 0 {
+                            m.score_move(m, None, &self.killers, None, None, history)
+                        } else {
+                            history[m.from_square() as usize][m.to_square() as usize] as i32
+                        };
+                        self.len += 1;
+                    }
+                    self.index = 0;
+                    self.stage = Stage::Killers;
+                }
+                Stage::Killers => {
+                    let Some(&killer) = self.killers.get(self.killer_index) else {
+                        self.stage = Stage::Quiets;
+                        continue;
+                    };
+                    self.killer_index += 1;
+                    // A killer comes from another position, it is only played when it is a move here too
+                    if Some(killer) != self.tt_move && self.moves[..self.len].contains(&killer) {
+                        return Some(killer);
+                    }
+                }
+                Stage::Quiets => {
+                    let Some(m) = self.pick_best() else {
+                        self.stage = Stage::BadCaptures;
+                        continue;
+                    };
+                    if Some(m) == self.tt_move || self.killers.contains(&m) {
+                        continue;
+                    }
+                    return Some(m);
+                }
+                Stage::BadCaptures => {
+                    let Some(&m) = self.bad_captures.iter().nth(self.bad_index) else {
+                        self.stage = Stage::Done;
+                        continue;
+                    };
+                    self.bad_index += 1;
+                    return Some(m);
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use crate::board::{self, Board, STARTPOS_FEN};
+use crate::san::SanError;
+use crate::util::{FenError, Move};
+use crate::mate_in;
+
+// The Seven Tag Roster, written first and in this order
+const ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+const LINE_WIDTH: usize = 80;
+
+// Why a game could not be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PgnError {
+    BadTag(String),
+    Fen(FenError), // the FEN tag does not hold a valid position
+    Move { ply: usize, error: SanError }, // ply counts from 1 in the line the move belongs to
+    UnbalancedVariation,
+    NoGame, // the text holds no tags and no moves
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::BadTag(tag) => write!(f, "malformed tag '{}'", tag),
+            PgnError::Fen(e) => write!(f, "invalid FEN tag: {}", e),
+            PgnError::Move { ply, error } => write!(f, "ply {}: {}", ply, error),
+            PgnError::UnbalancedVariation => write!(f, "unbalanced variation parentheses"),
+            PgnError::NoGame => write!(f, "no game found"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl From<FenError> for PgnError {
+    fn from(e: FenError) -> Self {
+        PgnError::Fen(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnMove {
+    pub mv: Move,
+    pub san: String,
+    pub nags: Vec<u8>, // `$1` or a `!` suffix is 1, `?` is 2 and so on
+    pub comment: Option<String>,
+    pub variations: Vec<Vec<PgnMove>>, // alternatives to this move, played from the position before it
+}
+
+impl PgnMove {
+    fn new(mv: Move, san: String) -> Self {
+        PgnMove { mv, san, nags: Vec::new(), comment: None, variations: Vec::new() }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>, // in file order
+    pub comment: Option<String>, // a comment before the first move
+    pub moves: Vec<PgnMove>, // the main line
+    pub result: String,
+}
+
+impl Default for PgnGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PgnGame {
+    // An empty game with the Seven Tag Roster filled with placeholders
+    pub fn new() -> Self {
+        let tags = ROSTER.iter().map(|&name| (name.to_string(), if name == "Result" { "*" } else { "?" }.to_string())).collect();
+        PgnGame { tags, comment: None, moves: Vec::new(), result: "*".to_string() }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+        if name == "Result" {
+            self.result = value.to_string();
+        }
+    }
+
+    pub fn set_result(&mut self, result: &str) {
+        self.set_tag("Result", result);
+    }
+
+    // The position the game starts from, the `FEN` tag if there is one
+    pub fn start_board(&self) -> Result<Board, FenError> {
+        Board::from_fen(self.tag("FEN").unwrap_or(STARTPOS_FEN))
+    }
+
+    // Every position of the main line, from the start position to the final one
+    pub fn replay(&self) -> Result<Vec<Board>, FenError> {
+        let mut board = self.start_board()?;
+        let mut boards = vec![board.clone()];
+        for pgn_move in &self.moves {
+            board::make_move(&mut board, &pgn_move.mv).unwrap();
+            boards.push(board.clone());
+        }
+        Ok(boards)
+    }
+
+    // The game result from White's side as the tuner uses it, None for unfinished games
+    pub fn result_value(&self) -> Option<f64> {
+        match self.result.as_str() {
+            "1-0" => Some(1.0),
+            "0-1" => Some(0.0),
+            "1/2-1/2" => Some(0.5),
+            _ => None,
+        }
+    }
+
+    // Appends a move played on `board` to the main line and plays it there
+    pub fn push_move(&mut self, board: &mut Board, mv: Move, comment: Option<String>) {
+        let mut pgn_move = PgnMove::new(mv, board.move_to_san(&mv));
+        pgn_move.comment = comment;
+        board::make_move(board, &mv).unwrap();
+        self.moves.push(pgn_move);
+    }
+}
+
+// An engine eval as a move comment, `+0.35/12` in pawns from White's side or `+M5/20` for mates
+pub fn eval_comment(white_score: i32, depth: i32) -> String {
+    match mate_in(white_score) {
+        Some(moves) if moves > 0 => format!("+M{}/{}", moves, depth),
+        Some(moves) => format!("-M{}/{}", -moves, depth),
+        None => format!("{}{:.2}/{}", if white_score >= 0 { "+" } else { "-" }, white_score.abs() as f64 / 100.0, depth),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    BadTag(String),
+    Comment(String),
+    Nag(u8),
+    Open,
+    Close,
+    Move(String),
+    Result(String),
+}
+
+fn is_result(word: &str) -> bool {
+    matches!(word, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+fn parse_tag(inner: &str) -> Option<(String, String)> {
+    let inner = inner.trim();
+    let name_end = inner.find(char::is_whitespace)?;
+    let value = inner[name_end..].trim();
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    Some((inner[..name_end].to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")))
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut line_start = true;
+    while i < chars.len() {
+        let c = chars[i];
+        let at_line_start = line_start;
+        line_start = c == '\n';
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        match c {
+            // Escaped lines and `;` comments run to the end of the line
+            '%' | ';' if c == ';' || at_line_start => {
+                while i < chars.len() && chars[i] != '\n' { i += 1; }
+                if c == ';' {
+                    tokens.push(Token::Comment(chars[start + 1..i].iter().collect::<String>().trim().to_string()));
+                }
+            }
+            '{' => {
+                while i < chars.len() && chars[i] != '}' { i += 1; }
+                let comment: String = chars[start + 1..i].iter().collect();
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<_>>().join(" ")));
+                i += 1;
+            }
+            '[' => {
+                // Quoted values may hold `]`
+                let mut quoted = false;
+                i += 1;
+                while i < chars.len() && (quoted || chars[i] != ']') {
+                    if chars[i] == '\\' { i += 1; } else if chars[i] == '"' { quoted = !quoted; }
+                    i += 1;
+                }
+                let inner: String = chars[start + 1..i.min(chars.len())].iter().collect();
+                tokens.push(match parse_tag(&inner) {
+                    Some((name, value)) => Token::Tag(name, value),
+                    None => Token::BadTag(inner),
+                });
+                i += 1;
+            }
+            '(' => { tokens.push(Token::Open); i += 1; }
+            ')' => { tokens.push(Token::Close); i += 1; }
+            '$' => {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() { i += 1; }
+                let nag: String = chars[start + 1..i].iter().collect();
+                tokens.push(Token::Nag(nag.parse().unwrap_or(0)));
+            }
+            _ => {
+                while i < chars.len() && !chars[i].is_whitespace() && !"(){}[];$".contains(chars[i]) { i += 1; }
+                let word: String = chars[start..i].iter().collect();
+                if is_result(&word) {
+                    tokens.push(Token::Result(word));
+                    continue;
+                }
+                // Move numbers, `12.` and `12...`, may be glued to the move. Only digits ended by a dot
+                // are a number, so castling written `0-0` keeps its zero
+                let unnumbered = word.trim_start_matches(|c: char| c.is_ascii_digit());
+                let word = if unnumbered.is_empty() || unnumbered.starts_with('.') { unnumbered.trim_start_matches('.') } else { word.as_str() };
+                if word.is_empty() {
+                    continue;
+                }
+                let san = word.trim_end_matches(['!', '?']);
+                tokens.push(Token::Move(san.to_string()));
+                if let Some(nag) = suffix_nag(&word[san.len()..]) {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+        }
+    }
+    tokens
+}
+
+// Reads one line of moves from `board`, recursing into variations. The board is left where it was
+fn parse_line(tokens: &[Token], idx: &mut usize, board: &mut Board, game_comment: &mut Option<String>) -> Result<Vec<PgnMove>, PgnError> {
+    let mut line: Vec<PgnMove> = Vec::new();
+    while *idx < tokens.len() {
+        match &tokens[*idx] {
+            Token::Move(san) => {
+                let mv = board.parse_san(san).map_err(|error| PgnError::Move { ply: line.len() + 1, error })?;
+                let san = board.move_to_san(&mv);
+                board::make_move(board, &mv).unwrap();
+                line.push(PgnMove::new(mv, san));
+            }
+            Token::Nag(nag) => {
+                if let Some(last) = line.last_mut() {
+                    last.nags.push(*nag);
+                }
+            }
+            Token::Comment(text) => {
+                let target = match line.last_mut() {
+                    Some(last) => &mut last.comment,
+                    None => &mut *game_comment,
+                };
+                match target {
+                    Some(existing) => { existing.push(' '); existing.push_str(text); }
+                    None => *target = Some(text.clone()),
+                }
+            }
+            Token::Open => {
+                // A variation replaces the last move, so it starts from the position before it
+                if line.is_empty() {
+                    return Err(PgnError::UnbalancedVariation);
+                }
+                *idx += 1;
+                board::undo_move(board).unwrap();
+                let variation = parse_line(tokens, idx, board, &mut None)?;
+                if tokens.get(*idx) != Some(&Token::Close) {
+                    return Err(PgnError::UnbalancedVariation);
+                }
+                let last = line.last_mut().unwrap();
+                board::make_move(board, &last.mv).unwrap();
+                last.variations.push(variation);
+            }
+            Token::Close | Token::Result(_) | Token::Tag(..) | Token::BadTag(_) => break,
+        }
+        *idx += 1;
+    }
+    for _ in &line {
+        board::undo_move(board).unwrap();
+    }
+    Ok(line)
+}
+
+// Reads every game of a PGN file. A game that fails to parse does not stop the ones after it
+pub fn read_games(text: &str) -> Vec<Result<PgnGame, PgnError>> {
+    let tokens = tokenize(text);
+    let mut games = Vec::new();
+    let mut idx = 0;
+    while idx < tokens.len() {
+        games.push(read_game(&tokens, &mut idx));
+        // Skip what is left of a game that failed, up to the next tag section
+        while idx < tokens.len() && !matches!(tokens[idx], Token::Tag(..) | Token::BadTag(_)) {
+            idx += 1;
+        }
+    }
+    games
+}
+
+// Reads the first game of a PGN text
+pub fn read_game_str(text: &str) -> Result<PgnGame, PgnError> {
+    read_games(text).into_iter().next().unwrap_or(Err(PgnError::NoGame))
+}
+
+fn read_game(tokens: &[Token], idx: &mut usize) -> Result<PgnGame, PgnError> {
+    let mut game = PgnGame { tags: Vec::new(), comment: None, moves: Vec::new(), result: "*".to_string() };
+    while *idx < tokens.len() {
+        match &tokens[*idx] {
+            Token::Tag(name, value) => game.set_tag(name, value),
+            Token::BadTag(tag) => {
+                *idx += 1;
+                return Err(PgnError::BadTag(tag.clone()));
+            }
+            _ => break,
+        }
+        *idx += 1;
+    }
+    let mut board = game.start_board()?;
+    game.moves = parse_line(tokens, idx, &mut board, &mut game.comment)?;
+    match tokens.get(*idx) {
+        Some(Token::Result(result)) => {
+            game.result = result.clone();
+            *idx += 1;
+        }
+        Some(Token::Close) => return Err(PgnError::UnbalancedVariation),
+        _ => {}
+    }
+    if game.tags.is_empty() && game.moves.is_empty() && game.comment.is_none() {
+        return Err(PgnError::NoGame);
+    }
+    Ok(game)
+}
+
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Appends the movetext of a line, numbering moves from `ply` (0 is White's first move)
+fn write_line(words: &mut Vec<String>, line: &[PgnMove], mut ply: usize) {
+    let mut need_number = true;
+    for pgn_move in line {
+        // The move number stays on the same line as its move
+        if ply.is_multiple_of(2) {
+            words.push(format!("{}. {}", ply / 2 + 1, pgn_move.san));
+        } else if need_number {
+            words.push(format!("{}... {}", ply / 2 + 1, pgn_move.san));
+        } else {
+            words.push(pgn_move.san.clone());
+        }
+        words.extend(pgn_move.nags.iter().map(|nag| format!("${}", nag)));
+        need_number = false;
+        if let Some(comment) = &pgn_move.comment {
+            words.push(format!("{{{}}}", comment));
+            need_number = true;
+        }
+        // Parentheses stick to the first and last word of the variation
+        for variation in pgn_move.variations.iter().filter(|v| !v.is_empty()) {
+            let first = words.len();
+            write_line(words, variation, ply);
+            words[first].insert(0, '(');
+            words.last_mut().unwrap().push(')');
+            need_number = true;
+        }
+        ply += 1;
+    }
+}
+
+// Writes a game in export format, wrapped at 80 columns
+impl std::fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for name in ROSTER {
+            let value = if name == "Result" { self.result.as_str() } else { self.tag(name).unwrap_or("?") };
+            writeln!(f, "[{} \"{}\"]", name, escape_tag(value))?;
+        }
+        for (name, value) in self.tags.iter().filter(|(n, _)| !ROSTER.contains(&n.as_str())) {
+            writeln!(f, "[{} \"{}\"]", name, escape_tag(value))?;
+        }
+        writeln!(f)?;
+
+        // Games from a FEN may start with Black to move and at any move number
+        let start_ply = self.start_board()
+            .map(|board| (board.fullmove_number as usize).saturating_sub(1) * 2 + if board.move_color == 1 { 0 } else { 1 })
+            .unwrap_or(0);
+        let mut words = Vec::new();
+        if let Some(comment) = &self.comment {
+            words.push(format!("{{{}}}", comment));
+        }
+        write_line(&mut words, &self.moves, start_ply);
+        words.push(self.result.clone());
+
+        let mut width = 0;
+        for word in words {
+            if width > 0 && width + 1 + word.len() > LINE_WIDTH {
+                writeln!(f)?;
+                width = 0;
+            } else if width > 0 {
+                write!(f, " ")?;
+                width += 1;
+            }
+            write!(f, "{}", word)?;
+            width += word.len();
+        }
+        writeln!(f)
+    }
+}
@@ -0,0 +1,203 @@
+use crate::board::{self, Board, BBPiece};
+use crate::util::{self, Move, MoveFlag, MoveStack};
+
+// Why `parse_san` rejected a move
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SanError {
+    Empty,
+    Malformed(String),
+    Illegal(String), // well formed, but no legal move matches it
+    Ambiguous(String), // more than one legal move matches, a disambiguation is missing
+}
+
+impl std::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SanError::Empty => write!(f, "empty move"),
+            SanError::Malformed(s) => write!(f, "malformed SAN move '{}'", s),
+            SanError::Illegal(s) => write!(f, "illegal move '{}'", s),
+            SanError::Ambiguous(s) => write!(f, "ambiguous move '{}'", s),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+fn piece_letter(piece: BBPiece) -> char {
+    match piece {
+        BBPiece::Knight => 'N',
+        BBPiece::Bishop => 'B',
+        BBPiece::Rook => 'R',
+        BBPiece::Queen => 'Q',
+        BBPiece::King => 'K',
+        _ => 'P',
+    }
+}
+
+fn letter_piece(letter: char) -> Option<BBPiece> {
+    match letter {
+        'N' => Some(BBPiece::Knight),
+        'B' => Some(BBPiece::Bishop),
+        'R' => Some(BBPiece::Rook),
+        'Q' => Some(BBPiece::Queen),
+        'K' => Some(BBPiece::King),
+        _ => None,
+    }
+}
+
+fn piece_on(board: &Board, square: usize) -> Option<BBPiece> {
+    [BBPiece::Pawn, BBPiece::Knight, BBPiece::Bishop, BBPiece::Rook, BBPiece::Queen, BBPiece::King]
+        .into_iter()
+        .find(|&piece| board.get([piece], square))
+}
+
+fn is_castle(mv: &Move) -> bool {
+    mv.flags() == MoveFlag::KingCastle as u8 || mv.flags() == MoveFlag::QueenCastle as u8
+}
+
+fn promotion_piece(mv: &Move) -> Option<BBPiece> {
+    if mv.flags() & 0x8 == 0 {
+        return None;
+    }
+    Some(match mv.flags() & 0x3 {
+        0 => BBPiece::Knight,
+        1 => BBPiece::Bishop,
+        2 => BBPiece::Rook,
+        _ => BBPiece::Queen,
+    })
+}
+
+impl Board {
+    // Legal moves of the side to move, leaving `self.moves` as it was
+    fn san_legal_moves(&mut self) -> MoveStack {
+        let saved = self.moves;
+        self.gen_moves(true, false);
+        let legal = self.moves;
+        self.moves = saved;
+        legal
+    }
+
+    // Formats a legal move as SAN, e.g. `Nbd7`, `exd8=Q+`, `O-O-O#`
+    pub fn move_to_san(&mut self, mv: &Move) -> String {
+        let from = mv.from_square() as usize;
+        let to = mv.to_square() as usize;
+        let mut san = String::new();
+        if mv.flags() == MoveFlag::KingCastle as u8 {
+            san.push_str("O-O");
+        } else if mv.flags() == MoveFlag::QueenCastle as u8 {
+            san.push_str("O-O-O");
+        } else {
+            let piece = piece_on(self, from).unwrap_or(BBPiece::Pawn);
+            let capture = mv.flags() & MoveFlag::Capture as u8 != 0;
+            let from_name = util::idx_to_sq(from);
+            if piece == BBPiece::Pawn {
+                if capture {
+                    san.push_str(&from_name[..1]);
+                }
+            } else {
+                san.push(piece_letter(piece));
+                // Other pieces of the same kind that can reach the same square
+                let rivals: Vec<usize> = self.san_legal_moves().iter()
+                    .filter(|m| m.to_square() as usize == to && m.from_square() as usize != from && !is_castle(m))
+                    .map(|m| m.from_square() as usize)
+                    .filter(|&sq| piece_on(self, sq) == Some(piece))
+                    .collect();
+                if !rivals.is_empty() {
+                    if rivals.iter().all(|&sq| sq % 8 != from % 8) {
+                        san.push_str(&from_name[..1]);
+                    } else if rivals.iter().all(|&sq| sq / 8 != from / 8) {
+                        san.push_str(&from_name[1..]);
+                    } else {
+                        san.push_str(&from_name);
+                    }
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&util::idx_to_sq(to));
+            if let Some(promo) = promotion_piece(mv) {
+                san.push('=');
+                san.push(piece_letter(promo));
+            }
+        }
+        // Check and mate suffixes
+        if board::make_move(self, mv).is_ok() {
+            if board::is_check(self) {
+                san.push(if self.san_legal_moves().is_empty() { '#' } else { '+' });
+            }
+            board::undo_move(self).unwrap();
+        }
+        san
+    }
+
+    // Finds the legal move a SAN string stands for. Check, mate and annotation suffixes are
+    // ignored, `0-0` is read as `O-O` and the `=` of promotions is optional
+    pub fn parse_san(&mut self, san: &str) -> Result<Move, SanError> {
+        let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        if text.is_empty() {
+            return Err(SanError::Empty);
+        }
+        let legal = self.san_legal_moves();
+        let malformed = || SanError::Malformed(san.to_string());
+        let mut candidates: Vec<Move> = Vec::new();
+
+        let castle = match text {
+            "O-O" | "0-0" => Some(MoveFlag::KingCastle),
+            "O-O-O" | "0-0-0" => Some(MoveFlag::QueenCastle),
+            _ => None,
+        };
+        if let Some(flag) = castle {
+            candidates.extend(legal.iter().filter(|m| m.flags() == flag as u8));
+        } else {
+            let mut chars: Vec<char> = text.chars().collect();
+            let piece = match letter_piece(chars[0]) {
+                Some(piece) => { chars.remove(0); piece }
+                None => BBPiece::Pawn,
+            };
+            // Promotion, written `e8=Q` or `e8Q`
+            let mut promotion = None;
+            if let Some(promo) = chars.last().and_then(|&c| letter_piece(c)).filter(|&p| p != BBPiece::King) {
+                promotion = Some(promo);
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+            if chars.len() < 2 {
+                return Err(malformed());
+            }
+            let dest: String = chars[chars.len() - 2..].iter().collect();
+            let (file, rank) = (chars[chars.len() - 2], chars[chars.len() - 1]);
+            if !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+                return Err(malformed());
+            }
+            let to = util::sq_to_idx(&dest);
+            // Whatever is left is the capture mark and the disambiguation
+            let mut from_file = None;
+            let mut from_rank = None;
+            for &c in &chars[..chars.len() - 2] {
+                match c {
+                    'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some(c as usize - 'a' as usize),
+                    '1'..='8' if from_rank.is_none() => from_rank = Some(c as usize - '1' as usize),
+                    'x' | ':' | '-' => {}
+                    _ => return Err(malformed()),
+                }
+            }
+            candidates.extend(legal.iter().filter(|m| {
+                let from = m.from_square() as usize;
+                m.to_square() as usize == to
+                    && !is_castle(m)
+                    && piece_on(self, from) == Some(piece)
+                    && promotion_piece(m) == promotion
+                    && from_file.is_none_or(|f| from % 8 == f)
+                    && from_rank.is_none_or(|r| from / 8 == r)
+            }));
+        }
+        match candidates.len() {
+            0 => Err(SanError::Illegal(san.to_string())),
+            1 => Ok(candidates[0]),
+            _ => Err(SanError::Ambiguous(san.to_string())),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// EPD test-suite runner for best-move suites like WAC, STS and ECM:
+// `bytechess epd <file> [movetime MS | depth N | nodes N] [threads N] [hash MB]`
+use std::time::Instant;
+use std::sync::atomic::Ordering;
+use crate::{think, SearchLimits, SearchState};
+use crate::epd::{self, Epd};
+use crate::util::Move;
+
+const DEFAULT_MOVETIME: u64 = 1000; // ms per position when no limit is given
+
+// The points an STS `c0` comment gives for each move, `c0 "f5=10, Be5+=2, e8=Q=3"`, split at the
+// last `=` so a promotion keeps its own
+fn sts_points(record: &Epd) -> Vec<(Move, u32)> {
+    let mut board = record.board.clone();
+    let Some(comment) = record.get_first("c0") else {
+        return Vec::new();
+    };
+    comment.split(',').filter_map(|entry| {
+        let (san, points) = entry.trim().rsplit_once('=')?;
+        Some((board.parse_san(san).ok()?, points.trim().parse().ok()?))
+    }).collect()
+}
+
+pub fn main(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = args.first() else {
+        return Err("usage: bytechess epd <file> [movetime MS | depth N | nodes N] [threads N] [hash MB]".into());
+    };
+    let mut limits = SearchLimits::default();
+    let mut threads = 1;
+    let mut hash_mb = 64;
+    for pair in args[1..].chunks(2) {
+        let value: u64 = pair.get(1).ok_or(format!("missing value for '{}'", pair[0]))?.parse()?;
+        match pair[0].as_str() {
+            "movetime" => limits.movetime = Some(value),
+            "depth" => limits.depth = Some(value as i32),
+            "nodes" => limits.nodes = Some(value),
+            "threads" => threads = value.max(1) as usize,
+            "hash" => hash_mb = value.max(1) as usize,
+            option => return Err(format!("unknown option '{}'", option).into()),
+        }
+    }
+    if limits.movetime.is_none() && limits.depth.is_none() && limits.nodes.is_none() {
+        limits.movetime = Some(DEFAULT_MOVETIME);
+    }
+
+    let text = std::fs::read_to_string(path)?;
+    let mut state = SearchState::new(hash_mb);
+    state.set_threads(threads);
+    state.search_info.quiet = true;
+
+    let (mut positions, mut solved, mut points, mut max_points) = (0, 0, 0, 0);
+    let suite_start = Instant::now();
+    for (line, record) in epd::read_epds(&text) {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                println!("{:>4}  skipped, {}", line, e);
+                continue;
+            }
+        };
+        let id = record.get_first("id").unwrap_or("-").to_string();
+        let (best, avoid) = match (record.moves("bm"), record.moves("am")) {
+            (Ok(best), Ok(avoid)) if !best.is_empty() || !avoid.is_empty() => (best, avoid),
+            (Err(e), _) | (_, Err(e)) => {
+                println!("{:>4}  {:<14} skipped, {}", line, id, e);
+                continue;
+            }
+            _ => {
+                println!("{:>4}  {:<14} skipped, no bm or am", line, id);
+                continue;
+            }
+        };
+        let correct = |mv: &Move| (best.is_empty() || best.contains(mv)) && !avoid.contains(mv);
+
+        // Every position is searched from a clean state, so a run is reproducible
+        state.clear();
+        state.search_info.signals.stop.store(false, Ordering::Relaxed);
+        let timer = Instant::now();
+        let mut board = record.board.clone();
+        let result = think(&mut board, &limits, timer, &mut state);
+        let elapsed = timer.elapsed().as_millis() as u64;
+
+        positions += 1;
+        let is_solved = correct(&result.best_move);
+        // Solved from the first iteration that found a correct move and kept one until the end
+        let mut solve = (result.depth.max(0) + 1, elapsed);
+        for &(depth, mv, ms) in result.iterations.iter().rev() {
+            if !correct(&mv) {
+                break;
+            }
+            solve = (depth, ms);
+        }
+        let mut board = record.board.clone();
+        let played = board.move_to_san(&result.best_move);
+        let sts = sts_points(&record);
+        let position_points = sts.iter().find(|(mv, _)| *mv == result.best_move).map_or(0, |&(_, p)| p);
+        points += position_points;
+        max_points += sts.iter().map(|&(_, p)| p).max().unwrap_or(0);
+        let sts_column = if sts.is_empty() { String::new() } else { format!("  {:>2} pts", position_points) };
+
+        if is_solved {
+            solved += 1;
+            println!("{:>4}  {:<14} solved  {:<8} depth {:>3}  time {:>7} ms{}", line, id, played, solve.0, solve.1, sts_column);
+        } else {
+            let expected = if best.is_empty() {
+                format!("am {}", record.get("am").unwrap_or(&[]).join(" "))
+            } else {
+                format!("bm {}", record.get("bm").unwrap_or(&[]).join(" "))
+            };
+            println!("{:>4}  {:<14} failed  {:<8} ({}){}", line, id, played, expected, sts_column);
+        }
+    }
+
+    println!();
+    println!("Solved {}/{} ({:.1}%), failed {}", solved, positions, solved as f64 * 100.0 / positions.max(1) as f64, positions - solved);
+    if max_points > 0 {
+        println!("Score {}/{} ({:.1}%)", points, max_points, points as f64 * 100.0 / max_points as f64);
+    }
+    println!("Total time {:.1}s", suite_start.elapsed().as_secs_f64());
+    Ok(())
+}
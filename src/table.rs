@@ -1,7 +1,10 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
 use crate::util::{Move, Score};
+use crate::MATE_THRESHOLD;
 
 const DEFAULT_TT_SIZE_MB: usize = 256;
+pub const DEFAULT_PAWN_TT_SIZE_MB: usize = 32; // the `PawnHash` default, 1M entries
 
 // The type of bound stored in the table
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -58,17 +61,68 @@ impl TTEntry {
     pub fn get_depth(&self) -> i32 {
         self.depth as i32
     }
+
+    // Packs everything but the key into one word:
+    // bits 0-15 move, 16-23 depth, 24-25 bound, 26-31 age, 32-63 score
+    fn data(&self) -> u64 {
+        (self.best_move as u64)
+            | (self.depth as u64) << 16
+            | ((self.bound & 0x3) as u64) << 24
+            | ((self.age & TranspositionTable::AGE_MASK) as u64) << 26
+            | (self.score as u32 as u64) << 32
+    }
+
+    fn from_data(zobrist: u64, data: u64) -> Self {
+        TTEntry {
+            zobrist,
+            best_move: data as u16,
+            depth: (data >> 16) as u8,
+            bound: ((data >> 24) & 0x3) as u8,
+            age: ((data >> 26) as u8) & TranspositionTable::AGE_MASK,
+            _pad: 0,
+            score: (data >> 32) as u32 as i32,
+        }
+    }
+}
+// Mate scores count plies from the root, the table keeps them relative to the stored node
+// so an entry reached again at a different ply still reports the right mate distance
+fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score + ply
+    } else if score <= -MATE_THRESHOLD {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score - ply
+    } else if score <= -MATE_THRESHOLD {
+        score + ply
+    } else {
+        score
+    }
+}
+// A table slot that can be read and written by several search threads without locks.
+// The key is stored xor'ed with the data, so a torn write from two threads racing on
+// the same slot fails the key check on probe instead of returning a mixed entry.
+#[derive(Default)]
+struct AtomicTTEntry {
+    key: AtomicU64,
+    data: AtomicU64,
 }
 #[derive(Copy, Clone)]
 pub struct PawnEntry {
     pub zobrist: u64,      // Zobrist hash of the position
     pub score: Score,        // Score 
 }
-// The transposition table itself
+// The transposition table itself, shared between all search threads
 pub struct TranspositionTable {
-    pub table: Vec<Option<TTEntry>>,
+    table: Vec<AtomicTTEntry>,
     pub mask: usize,
-    pub age: u8,
+    age: AtomicU8,
 }
 pub struct PawnTable {
     pub table: Vec<Option<PawnEntry>>,
@@ -76,14 +130,25 @@ pub struct PawnTable {
 }
 
 impl PawnTable {
-    pub fn new() -> Self {
-        let size = 1 << 20; // 1M entries
+    pub fn new(size_mb: usize) -> Self {
+        let entry_size = std::mem::size_of::<Option<PawnEntry>>();
+        let raw_size = size_mb * 1024 * 1024 / entry_size;
+        // `idx = hash & mask` requires power-of-two table size.
+        let size = if raw_size.is_power_of_two() {
+            raw_size
+        } else {
+            raw_size.next_power_of_two() >> 1
+        }.max(1);
         Self {
             table: vec![None; size],
             mask: size - 1,
         }
     }
 
+    pub fn clear(&mut self) {
+        self.table.fill(None);
+    }
+
     pub fn store(&mut self, entry: PawnEntry) {
         let idx = (entry.zobrist as usize) & self.mask;
         self.table[idx] = Some(entry);
@@ -96,8 +161,10 @@ impl PawnTable {
 }
 
 impl TranspositionTable {
+    const AGE_MASK: u8 = 0x3F; // ages wrap at 64, they only need to tell this search from older ones
+
     pub fn new(size_mb: usize) -> Self {
-        let entry_size = std::mem::size_of::<TTEntry>();
+        let entry_size = std::mem::size_of::<AtomicTTEntry>();
         let target_size_bytes = size_mb * 1024 * 1024;
         let raw_size = target_size_bytes / entry_size;
         // `idx = hash & mask` requires power-of-two table size.
@@ -106,31 +173,72 @@ impl TranspositionTable {
         } else {
             raw_size.next_power_of_two() >> 1
         }.max(1);
+        let mut table = Vec::with_capacity(size);
+        table.resize_with(size, AtomicTTEntry::default);
         Self {
-            table: vec![None; size],
+            table,
             mask: size - 1,
-            age: 0,
+            age: AtomicU8::new(0),
         }
     }
 
-    pub fn store(&mut self, entry: TTEntry) {
+    pub fn store(&self, mut entry: TTEntry, ply: i32) {
+        entry.score = score_to_tt(entry.score, ply);
         let idx = (entry.zobrist as usize) & self.mask;
+        let slot = &self.table[idx];
         // Replace if new entry is deeper or from a newer search
-        let replace = match &self.table[idx] {
-            Some(old) => entry.depth > old.depth || entry.age > old.age,
-            None => true,
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let replace = if old_data == 0 {
+            true
+        } else {
+            let old = TTEntry::from_data(0, old_data);
+            entry.depth > old.depth || entry.age != old.age
         };
         if replace {
-            self.table[idx] = Some(entry);
+            let data = entry.data();
+            slot.key.store(entry.zobrist ^ data, Ordering::Relaxed);
+            slot.data.store(data, Ordering::Relaxed);
         }
     }
 
-    pub fn probe(&self, zobrist: u64) -> Option<&TTEntry> {
+    pub fn probe(&self, zobrist: u64, ply: i32) -> Option<TTEntry> {
         let idx = (zobrist as usize) & self.mask;
-        self.table[idx].as_ref().filter(|e| e.zobrist == zobrist)
+        let slot = &self.table[idx];
+        let data = slot.data.load(Ordering::Relaxed);
+        let key = slot.key.load(Ordering::Relaxed);
+        if data != 0 && key ^ data == zobrist {
+            let mut entry = TTEntry::from_data(zobrist, data);
+            entry.score = score_from_tt(entry.score, ply);
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    // Empties every slot, for `ucinewgame` and `Clear Hash`
+    pub fn clear(&self) {
+        for slot in &self.table {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    pub fn age(&self) -> u8 {
+        self.age.load(Ordering::Relaxed)
     }
 
-    pub fn next_age(&mut self) {
-        self.age = self.age.wrapping_add(1);
+    pub fn next_age(&self) {
+        let age = (self.age() + 1) & Self::AGE_MASK;
+        self.age.store(age, Ordering::Relaxed);
+    }
+    // Permille of the table filled by the current search, sampled from the first 1000 slots as UCI `hashfull`
+    pub fn hashfull(&self) -> usize {
+        let sample = self.table.len().min(1000);
+        let used = self.table[..sample].iter().filter(|slot| {
+            let data = slot.data.load(Ordering::Relaxed);
+            data != 0 && TTEntry::from_data(0, data).age == self.age()
+        }).count();
+        used * 1000 / sample
     }
 }
@@ -0,0 +1,70 @@
+use crate::SearchLimits;
+use crate::util::Move;
+
+// Time allocation for one move. The soft limit is checked between iterations and
+// stretched or shrunk as the search goes, the hard limit aborts the search inside `minimax`
+#[derive(Copy, Clone, Debug)]
+pub struct TimeManager {
+    soft_ms: u64, // base budget, before the stability and score scaling
+    hard_ms: u64,
+    prev_best: Option<Move>,
+    prev_score: i32,
+    stability: usize, // completed iterations in a row that kept the same best move
+    score_drop: i32, // how far the score fell in the last iteration, in centipawns
+}
+
+impl TimeManager {
+    pub const DEFAULT_OVERHEAD: u64 = 10; // ms, the `Move Overhead` default
+    // Multiplier of the soft limit for 0, 1, 2.. iterations with the same best move
+    const STABILITY_SCALE: [f64; 5] = [2.0, 1.4, 1.1, 0.9, 0.75];
+    const MAX_SCORE_DROP: i32 = 100;
+    const HARD_SCALE: u64 = 4; // the hard limit may go this far beyond the base budget
+
+    pub fn new(limits: &SearchLimits, overhead: u64) -> Self {
+        let (soft_ms, hard_ms) = if limits.infinite {
+            (u64::MAX, u64::MAX)
+        } else if let Some(movetime) = limits.movetime {
+            let movetime = movetime.saturating_sub(overhead).max(1);
+            (movetime, movetime)
+        } else if let Some(time) = limits.time {
+            // Never plan to use more than what is left once the GUI and network lag is paid for
+            let available = time.saturating_sub(overhead).max(1);
+            // 5% of time + half increment, or more when the next time control is close
+            let moves_left = limits.movestogo.map_or(20, |m| (m + 1).min(20));
+            let soft = (available / moves_left + limits.inc / 2).min(available / 2).max(1);
+            let hard = (soft * Self::HARD_SCALE).min(available * 3 / 4).max(soft);
+            (soft, hard)
+        } else {
+            (u64::MAX, u64::MAX) // depth, nodes or mate only
+        };
+        Self { soft_ms, hard_ms, prev_best: None, prev_score: 0, stability: 0, score_drop: 0 }
+    }
+    // Past this the search is aborted mid-iteration
+    pub fn hard_limit(&self) -> u64 {
+        self.hard_ms
+    }
+    // Current soft limit, longer when the best move keeps changing or the score is falling
+    pub fn soft_limit(&self) -> u64 {
+        if self.soft_ms == u64::MAX {
+            return u64::MAX;
+        }
+        let stability = Self::STABILITY_SCALE[self.stability.min(Self::STABILITY_SCALE.len() - 1)];
+        let drop = 1.0 + self.score_drop.clamp(0, Self::MAX_SCORE_DROP) as f64 / Self::MAX_SCORE_DROP as f64;
+        ((self.soft_ms as f64 * stability * drop) as u64).min(self.hard_ms)
+    }
+    // Records the result of a completed iteration
+    pub fn update(&mut self, best_move: Move, score: i32) {
+        if self.prev_best == Some(best_move) {
+            self.stability += 1;
+        } else {
+            self.stability = 0;
+        }
+        self.score_drop = if self.prev_best.is_some() { self.prev_score - score } else { 0 };
+        self.prev_best = Some(best_move);
+        self.prev_score = score;
+    }
+    // Whether a new iteration should not be started, `elapsed` being the time counted against our clock
+    pub fn should_stop(&self, elapsed: u64) -> bool {
+        elapsed >= self.soft_limit()
+    }
+}
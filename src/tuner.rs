@@ -3,6 +3,7 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use crate::util::{self, Score};
 use crate::tunereval::{self, evaluate};
+use crate::epd;
 
 // Global engine parameters that the evaluation function will use
 static mut CURRENT_ENGINE_PARAMS: Option<EngineParams> = None;
@@ -237,6 +238,20 @@ impl TexelTuner {
                 }
                 continue;
             }
+
+            // Format 2: EPD with the game result in c9, "fen c9 \"1-0\";"
+            if let Ok(record) = epd::Epd::parse(line) {
+                let result = match record.get_first("c9") {
+                    Some("1-0") => Some(1.0),
+                    Some("0-1") => Some(0.0),
+                    Some("1/2-1/2") => Some(0.5),
+                    _ => None,
+                };
+                if let Some(result) = result {
+                    positions.push(TrainingPosition { fen: record.board.to_string(), result });
+                    count += 1;
+                }
+            }
         }
 
         println!("Loaded {} training positions from {}", positions.len(), filename);
@@ -765,6 +780,12 @@ impl EngineParams {
         }
     }
 }
+
+impl Default for EngineParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 // Function to set engine parameters globally
 pub fn set_engine_params(params: EngineParams) {
     unsafe {
@@ -780,7 +801,7 @@ fn evaluate_fen(params: EngineParams, fen: &str) -> Result<i32, String> {
         // Load FEN into your board representation
         // Call your evaluate() function
         // Return centipawn evaluation
-        let board = util::board_from_fen(fen);
+        let board = crate::board::Board::from_fen(fen).map_err(|e| e.to_string())?;
         let eval = tunereval::evaluate(&board, &params);
         Ok(eval)
     }
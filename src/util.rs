@@ -4,6 +4,9 @@ use crate::board::{TOTAL_PHASE, KNIGHT_PHASE, BISHOP_PHASE, ROOK_PHASE, QUEEN_PH
 use crate::table::PawnEntry;
 use crate::table::PawnTable;
 use crate::{board, PIECE_VALUES, MOBILITY_VALUES};
+use std::sync::atomic::{AtomicBool, Ordering};
+// The `UCI_Chess960` option, castling moves are then written as the king taking its own rook
+pub static CHESS960: AtomicBool = AtomicBool::new(false);
 const KING_CENTER_BONUS: Score = Score::new(-18,19);
 //const DOUBLED_PAWN_PENALTY: Score = Score::new(1,1);
 //const ISOLATED_PAWN_PENALTY: Score = Score::new(5,5);
@@ -94,6 +97,11 @@ pub struct MoveStack {
     data: [Move; 218],
     len: usize,
 }
+impl Default for MoveStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl MoveStack {
     pub fn new() -> Self {
         Self {
@@ -252,8 +260,17 @@ impl MoveStack {
 impl std::fmt::Display for Move {
     /// Displays the move in UCI format (e.g., "a2a4")
      fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if *self == Move::null() {
+            return write!(f, "0000"); // the UCI null move, for `bestmove` when there is no legal move
+        }
         let from = idx_to_sq(self.from_square() as usize);
-        let to = idx_to_sq(self.to_square() as usize);
+        let mut to = idx_to_sq(self.to_square() as usize);
+        let castle = self.flags() == MoveFlag::KingCastle as u8 || self.flags() == MoveFlag::QueenCastle as u8;
+        if castle && !CHESS960.load(Ordering::Relaxed) {
+            // Standard chess writes castling as the two-square king move
+            let (king_to, _) = board::castling_destinations(self.from_square() as usize, self.flags() == MoveFlag::KingCastle as u8);
+            to = idx_to_sq(king_to);
+        }
         let promo = match self.flags() {
             x if x == MoveFlag::KnightPromotion as u8 || x == MoveFlag::KnightPromoCapture as u8 => Some('n'),
             x if x == MoveFlag::BishopPromotion as u8 || x == MoveFlag::BishopPromoCapture as u8 => Some('b'),
@@ -406,86 +423,191 @@ pub(crate) fn piece_to_fen(piece: BBPiece, color: Color) -> char {
     }
 }
 
+// Why `board_from_fen` rejected a FEN string
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    MissingFields(usize), // fewer than the 4 required fields
+    InvalidPiece(char),
+    WrongRankCount(usize),
+    WrongSquareCount { rank: usize, squares: usize },
+    KingCount { white: u32, black: u32 }, // each side needs exactly one king
+    PawnOnBackRank(String),
+    InvalidSideToMove(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    OpponentInCheck, // the side that just moved left its king in check
+}
+
+impl std::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::MissingFields(n) => write!(f, "expected at least 4 fields, got {}", n),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece character '{}'", c),
+            FenError::WrongRankCount(n) => write!(f, "expected 8 ranks, got {}", n),
+            FenError::WrongSquareCount { rank, squares } => write!(f, "rank {} has {} squares instead of 8", rank, squares),
+            FenError::KingCount { white, black } => write!(f, "expected one king per side, got {} white and {} black", white, black),
+            FenError::PawnOnBackRank(sq) => write!(f, "pawn on back rank square {}", sq),
+            FenError::InvalidSideToMove(s) => write!(f, "invalid side to move '{}'", s),
+            FenError::InvalidCastling(s) => write!(f, "invalid castling rights '{}'", s),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant square '{}'", s),
+            FenError::InvalidHalfmoveClock(s) => write!(f, "invalid halfmove clock '{}'", s),
+            FenError::InvalidFullmoveNumber(s) => write!(f, "invalid fullmove number '{}'", s),
+            FenError::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
 //Make function for board
-pub fn board_from_fen(fen: &str) -> board::Board {
+pub fn board_from_fen(fen: &str) -> Result<board::Board, FenError> {
     use crate::board::{Board, BBPiece};
     let mut bitboards = [0u64; 8];
-    let mut piece_on: [Option<BBPiece>; 64] = [None; 64];
-    let mut piece_moves = [0u32; 8];
     let mut castling_rights = [false; 4];
+    let mut castling_rooks = board::STANDARD_CASTLING_ROOKS;
     let mut en_passant = None;
     let mut halfmove_clock = 0u8;
     let mut fullmove_number = 1u16;
-    let mut move_color = 1;
 
     let parts: Vec<&str> = fen.split_whitespace().collect();
-    assert!(parts.len() >= 4, "Invalid FEN string");
+    if parts.len() < 4 {
+        return Err(FenError::MissingFields(parts.len()));
+    }
 
-    // Piece placement
-    let mut sq = 56; // Start at a8
+    // Piece placement, ranks come from the 8th down to the 1st
+    let ranks: Vec<&str> = parts[0].split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::WrongRankCount(ranks.len()));
+    }
     let mut material_score = Score::new(0,0);
-    for c in parts[0].chars() {
-        match c {
-            '/' => sq -= 16,
-            '1'..='8' => sq += c.to_digit(10).unwrap() as usize,
-            'P' => { bitboards[BBPiece::Pawn as usize] |= 1 << sq; bitboards[BBPiece::White as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::Pawn); sq += 1; material_score += PIECE_VALUES[BBPiece::Pawn as usize]; }
-            'N' => { bitboards[BBPiece::Knight as usize] |= 1 << sq; bitboards[BBPiece::White as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::Knight); sq += 1; material_score += PIECE_VALUES[BBPiece::Knight as usize];}
-            'B' => { bitboards[BBPiece::Bishop as usize] |= 1 << sq; bitboards[BBPiece::White as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::Bishop); sq += 1; material_score += PIECE_VALUES[BBPiece::Bishop as usize];}
-            'R' => { bitboards[BBPiece::Rook as usize] |= 1 << sq; bitboards[BBPiece::White as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::Rook); sq += 1; material_score += PIECE_VALUES[BBPiece::Rook as usize];}
-            'Q' => { bitboards[BBPiece::Queen as usize] |= 1 << sq; bitboards[BBPiece::White as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::Queen); sq += 1; material_score += PIECE_VALUES[BBPiece::Queen as usize];}
-            'K' => { bitboards[BBPiece::King as usize] |= 1 << sq; bitboards[BBPiece::White as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::King); sq += 1; material_score += PIECE_VALUES[BBPiece::King as usize];}
-            'p' => { bitboards[BBPiece::Pawn as usize] |= 1 << sq; bitboards[BBPiece::Black as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::Pawn); sq += 1; material_score -= PIECE_VALUES[BBPiece::Pawn as usize];}
-            'n' => { bitboards[BBPiece::Knight as usize] |= 1 << sq; bitboards[BBPiece::Black as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::Knight); sq += 1; material_score -= PIECE_VALUES[BBPiece::Knight as usize];}
-            'b' => { bitboards[BBPiece::Bishop as usize] |= 1 << sq; bitboards[BBPiece::Black as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::Bishop); sq += 1; material_score -= PIECE_VALUES[BBPiece::Bishop as usize];}
-            'r' => { bitboards[BBPiece::Rook as usize] |= 1 << sq; bitboards[BBPiece::Black as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::Rook); sq += 1; material_score -= PIECE_VALUES[BBPiece::Rook as usize];}
-            'q' => { bitboards[BBPiece::Queen as usize] |= 1 << sq; bitboards[BBPiece::Black as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::Queen); sq += 1; material_score -= PIECE_VALUES[BBPiece::Queen as usize];}
-            'k' => { bitboards[BBPiece::King as usize] |= 1 << sq; bitboards[BBPiece::Black as usize] |= 1 << sq; piece_on[sq] = Some(BBPiece::King); sq += 1; material_score -= PIECE_VALUES[BBPiece::King as usize];}
-            _ => {}
+    for (i, rank_str) in ranks.iter().enumerate() {
+        let rank = 7 - i;
+        let mut file = 0;
+        for c in rank_str.chars() {
+            if let Some(empty) = c.to_digit(10).filter(|n| (1..=8).contains(n)) {
+                file += empty as usize;
+                continue;
+            }
+            let piece = match c.to_ascii_lowercase() {
+                'p' => BBPiece::Pawn,
+                'n' => BBPiece::Knight,
+                'b' => BBPiece::Bishop,
+                'r' => BBPiece::Rook,
+                'q' => BBPiece::Queen,
+                'k' => BBPiece::King,
+                _ => return Err(FenError::InvalidPiece(c)),
+            };
+            if file < 8 {
+                let sq = rank * 8 + file;
+                bitboards[piece as usize] |= 1 << sq;
+                if c.is_ascii_uppercase() {
+                    bitboards[BBPiece::White as usize] |= 1 << sq;
+                    material_score += PIECE_VALUES[piece as usize];
+                } else {
+                    bitboards[BBPiece::Black as usize] |= 1 << sq;
+                    material_score -= PIECE_VALUES[piece as usize];
+                }
+            }
+            file += 1;
+        }
+        if file != 8 {
+            return Err(FenError::WrongSquareCount { rank: rank + 1, squares: file });
         }
     }
+    let kings = bitboards[BBPiece::King as usize];
+    let white_kings = (kings & bitboards[BBPiece::White as usize]).count_ones();
+    let black_kings = (kings & bitboards[BBPiece::Black as usize]).count_ones();
+    if white_kings != 1 || black_kings != 1 {
+        return Err(FenError::KingCount { white: white_kings, black: black_kings });
+    }
+    let back_rank_pawns = bitboards[BBPiece::Pawn as usize] & (0xFF | 0xFF << 56);
+    if back_rank_pawns != 0 {
+        return Err(FenError::PawnOnBackRank(idx_to_sq(back_rank_pawns.trailing_zeros() as usize)));
+    }
     let mut phase_count = TOTAL_PHASE;
-    phase_count -= bitboards[BBPiece::Knight as usize].count_ones() * KNIGHT_PHASE;
-    phase_count -= bitboards[BBPiece::Bishop as usize].count_ones() * BISHOP_PHASE;
-    phase_count -= bitboards[BBPiece::Rook as usize].count_ones() * ROOK_PHASE;
-    phase_count -= bitboards[BBPiece::Queen as usize].count_ones() * QUEEN_PHASE;
-    let phase = ((phase_count * 255 + TOTAL_PHASE/2)/TOTAL_PHASE) as u8; 
+    for (piece, piece_phase) in [(BBPiece::Knight, KNIGHT_PHASE), (BBPiece::Bishop, BISHOP_PHASE), (BBPiece::Rook, ROOK_PHASE), (BBPiece::Queen, QUEEN_PHASE)] {
+        phase_count -= bitboards[piece as usize].count_ones() as i32 * piece_phase;
+    }
+    let phase = board::phase_from_count(phase_count);
     // Active color
-    move_color = match parts[1] {
+    let move_color = match parts[1] {
         "w" => 1,
         "b" => -1,
-        _ => 1,
+        side => return Err(FenError::InvalidSideToMove(side.to_string())),
     };
 
-    // Castling rights
-    if parts[2].contains('K') { castling_rights[0] = true; }
-    if parts[2].contains('Q') { castling_rights[1] = true; }
-    if parts[2].contains('k') { castling_rights[2] = true; }
-    if parts[2].contains('q') { castling_rights[3] = true; }
+    // Castling rights, KQkq pick the outermost rook on that side of the king (X-FEN),
+    // file letters name the rook directly (Shredder-FEN) for Chess960 start positions
+    let invalid_castling = || FenError::InvalidCastling(parts[2].to_string());
+    for c in parts[2].chars().filter(|_| parts[2] != "-") {
+        let (color, rank, first_right) = if c.is_ascii_uppercase() { (BBPiece::White, 0, 0) } else { (BBPiece::Black, 56, 2) };
+        let back_rank = 0xFFu64 << rank;
+        let king_bb = bitboards[BBPiece::King as usize] & bitboards[color as usize] & back_rank;
+        let rooks = bitboards[BBPiece::Rook as usize] & bitboards[color as usize] & back_rank;
+        if king_bb == 0 {
+            return Err(invalid_castling());
+        }
+        let king_square = king_bb.trailing_zeros() as usize;
+        let rook_square = match c.to_ascii_uppercase() {
+            'K' => {
+                let king_side = rooks & u64::MAX.checked_shl(king_square as u32 + 1).unwrap_or(0);
+                if king_side == 0 { return Err(invalid_castling()); }
+                63 - king_side.leading_zeros() as usize
+            }
+            'Q' => {
+                let queen_side = rooks & ((1u64 << king_square) - 1);
+                if queen_side == 0 { return Err(invalid_castling()); }
+                queen_side.trailing_zeros() as usize
+            }
+            file @ 'A'..='H' => rank + (file as u8 - b'A') as usize,
+            _ => return Err(invalid_castling()),
+        };
+        if !bb_get(rooks, rook_square) || rook_square == king_square {
+            return Err(invalid_castling());
+        }
+        let right = first_right + if rook_square > king_square { 0 } else { 1 };
+        castling_rights[right] = true;
+        castling_rooks[right] = rook_square as u8;
+    }
 
-    // En passant
+    // En passant, the square behind a pawn of the side that just moved two squares
     if parts[3] != "-" {
-        en_passant = Some(crate::util::sq_to_idx(parts[3]));
+        let ep = parts[3].as_bytes();
+        let (ep_rank, pawn_dir): (u8, i32) = if move_color == 1 { (b'6', -8) } else { (b'3', 8) };
+        if ep.len() != 2 || !(b'a'..=b'h').contains(&ep[0]) || ep[1] != ep_rank {
+            return Err(FenError::InvalidEnPassant(parts[3].to_string()));
+        }
+        let sq = sq_to_idx(parts[3]);
+        let pawn_sq = (sq as i32 + pawn_dir) as usize;
+        let from_sq = (sq as i32 - pawn_dir) as usize;
+        let them = if move_color == 1 { BBPiece::Black } else { BBPiece::White };
+        let occupied = bitboards[BBPiece::White as usize] | bitboards[BBPiece::Black as usize];
+        if !bb_get(bitboards[BBPiece::Pawn as usize] & bitboards[them as usize], pawn_sq) || bb_get(occupied, sq) || bb_get(occupied, from_sq) {
+            return Err(FenError::InvalidEnPassant(parts[3].to_string()));
+        }
+        en_passant = Some(sq);
     }
 
-    // Halfmove clock
+    // Halfmove clock and fullmove number are optional, EPD positions leave them out
     if parts.len() > 4 {
-        halfmove_clock = parts[4].parse().unwrap_or(0);
+        halfmove_clock = parts[4].parse().map_err(|_| FenError::InvalidHalfmoveClock(parts[4].to_string()))?;
     }
-
-    // Fullmove number
     if parts.len() > 5 {
-        fullmove_number = parts[5].parse().unwrap_or(1);
+        fullmove_number = parts[5].parse().map_err(|_| FenError::InvalidFullmoveNumber(parts[5].to_string()))?;
     }
 
-    Board {
+    let mut board = Board {
         bitboards,
         move_color,
         castling_rights,
+        castling_rooks,
         en_passant,
         halfmove_clock,
         fullmove_number,
-        zobrist_hash: 0u64, //generated AFTER making a board
-        pawn_hash: 0u64, //generated AFTER making a board
+        zobrist_hash: 0u64, // filled in below, the hashes are computed from the finished board
+        pawn_hash: 0u64,
         moves: MoveStack::new(),
         state_history: Vec::new(),
         move_history: Vec::new(),
@@ -495,7 +617,13 @@ pub fn board_from_fen(fen: &str) -> board::Board {
         phase,
         phase_count,
         material_score,
+    };
+    if board.king_is_attacked() {
+        return Err(FenError::OpponentInCheck);
     }
+    board.zobrist_hash = crate::zobrist::zobrist_hash(&board);
+    board.pawn_hash = crate::zobrist::zobrist_pawn_hash(&board);
+    Ok(board)
 }
 // Print function for Board
 impl std::fmt::Display for board::Board {
@@ -556,11 +684,30 @@ impl std::fmt::Display for board::Board {
         )?;
 
         // Castling rights
+        // X-FEN: KQkq when the castling rook is the outermost one on its side, its file otherwise
         let mut castling = String::new();
-        if self.castling_rights[0] { castling.push('K'); }
-        if self.castling_rights[1] { castling.push('Q'); }
-        if self.castling_rights[2] { castling.push('k'); }
-        if self.castling_rights[3] { castling.push('q'); }
+        for right in 0..4 {
+            if !self.castling_rights[right] {
+                continue;
+            }
+            let color = if right < 2 { BBPiece::White } else { BBPiece::Black };
+            let rook_square = self.castling_rooks[right] as usize;
+            let rooks = self.bitboards[BBPiece::Rook as usize] & self.bitboards[color as usize] & (0xFFu64 << (rook_square / 8 * 8));
+            // No rook left on the rank means the right is stale, it is written by file rather than underflowing here
+            let outermost = if rooks == 0 {
+                None
+            } else if right % 2 == 0 {
+                Some(63 - rooks.leading_zeros() as usize)
+            } else {
+                Some(rooks.trailing_zeros() as usize)
+            };
+            let c = if outermost == Some(rook_square) {
+                if right % 2 == 0 { 'K' } else { 'Q' }
+            } else {
+                (b'A' + (rook_square % 8) as u8) as char
+            };
+            castling.push(if right < 2 { c } else { c.to_ascii_lowercase() });
+        }
         if castling.is_empty() {
             castling.push('-');
         }
@@ -1029,23 +1176,51 @@ fn evaluate_pawn_shelter(board: &board::Board, king_square: usize, is_white: boo
     penalty
 }
 
-pub fn perft(bd: &mut board::Board, depth: u8) -> u64 {
-    let mut count = 0;
+// Number of leaf positions `depth` plies below the board. With `bulk` the last ply is counted
+// from the legal move list instead of being played, much faster but make_move is not run there
+pub fn perft(bd: &mut board::Board, depth: u8, bulk: bool) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
     bd.gen_moves(true, false);
     let moves = bd.moves;
-    if depth <= 1
-    {
-        return moves.len() as u64
+    if depth == 1 && bulk {
+        return moves.len() as u64;
     }
+    let mut count = 0;
     for m in moves.iter() {
-        let orig = bd.clone();
-        board::make_move(bd, m);
-        if depth > 1 {
-            count += perft(bd, depth - 1);
-        } else {
-            count += 1;
-        }
-        board::undo_move(bd);
+        board::make_move(bd, m).unwrap();
+        count += perft(bd, depth - 1, bulk);
+        board::undo_move(bd).unwrap();
     }
     count
 }
+
+// Perft split by root move, the usual way to find a movegen bug by diffing against another engine
+pub fn perft_divide(bd: &mut board::Board, depth: u8, bulk: bool) -> Vec<(Move, u64)> {
+    bd.gen_moves(true, false);
+    let moves = bd.moves;
+    let mut divide = Vec::new();
+    for m in moves.iter() {
+        board::make_move(bd, m).unwrap();
+        divide.push((*m, perft(bd, depth.saturating_sub(1), bulk)));
+        board::undo_move(bd).unwrap();
+    }
+    divide
+}
+
+// Prints the divide as `e2e4: 20` lines sorted by move, then the total and the speed
+pub fn print_perft(bd: &mut board::Board, depth: u8, bulk: bool) {
+    let start = std::time::Instant::now();
+    let mut divide = if depth == 0 { Vec::new() } else { perft_divide(bd, depth, bulk) };
+    let elapsed = start.elapsed();
+    divide.sort_by_key(|(m, _)| m.to_string());
+    for (m, nodes) in &divide {
+        println!("{}: {}", m, nodes);
+    }
+    let total: u64 = if depth == 0 { 1 } else { divide.iter().map(|(_, nodes)| nodes).sum() };
+    println!();
+    println!("Nodes searched: {}", total);
+    println!("Time: {} ms", elapsed.as_millis());
+    println!("NPS: {}", (total as f64 / elapsed.as_secs_f64().max(1e-9)) as u64);
+}
@@ -0,0 +1,72 @@
+// EPD reading and writing: operations and their quoted operands, the clocks, move opcodes and errors
+use bytechess::epd::{self, Epd, EpdError};
+use bytechess::san::SanError;
+
+const WAC_001: &str = r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001";"#;
+
+#[test]
+fn epd_operations() {
+    let record = Epd::parse(WAC_001).unwrap();
+    assert_eq!(record.get_first("id"), Some("WAC.001"));
+    assert_eq!(record.get("bm"), Some(&["Qg6".to_string()][..]));
+    assert_eq!(record.get("am"), None);
+    let best: Vec<String> = record.moves("bm").unwrap().iter().map(|m| m.to_string()).collect();
+    assert_eq!(best, ["g3g6"]);
+
+    // Several moves, a quoted operand holding `;` and an escaped quote, and a last operation without its `;`
+    let record = Epd::parse(r#"4k3/8/8/8/8/8/4P3/4K3 w - - bm e4 e3; am Kd1; c0 "a; \"b\""; id "x y""#).unwrap();
+    assert_eq!(record.moves("bm").unwrap().len(), 2);
+    assert_eq!(record.moves("am").unwrap()[0].to_string(), "e1d1");
+    assert_eq!(record.get_first("c0"), Some("a; \"b\""));
+    assert_eq!(record.get_first("id"), Some("x y"));
+    // An opcode with no operands
+    let record = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - noop;").unwrap();
+    assert_eq!(record.get("noop"), Some(&[][..]));
+}
+
+#[test]
+fn epd_clocks() {
+    // Without clocks the board gets the FEN defaults
+    let record = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 b - -").unwrap();
+    assert_eq!((record.board.halfmove_clock, record.board.fullmove_number), (0, 1));
+    assert!(record.operations.is_empty());
+    // From the opcodes, or from FEN fields, which then become the opcodes
+    let record = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 b - - hmvc 7; fmvn 30;").unwrap();
+    assert_eq!((record.board.halfmove_clock, record.board.fullmove_number), (7, 30));
+    let record = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 b - - 7 30 id \"clocks\";").unwrap();
+    assert_eq!((record.board.halfmove_clock, record.board.fullmove_number), (7, 30));
+    assert_eq!(record.to_string(), "4k3/8/8/8/8/8/4P3/4K3 b - - fmvn 30; hmvc 7; id \"clocks\";");
+}
+
+#[test]
+fn epd_write_read_round_trip() {
+    let mut record = Epd::parse(WAC_001).unwrap();
+    record.set("c0", vec!["a \"quoted\" comment".to_string()]);
+    record.set("ce", vec!["350".to_string()]);
+    let written = record.to_string();
+    assert_eq!(written, r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; c0 "a \"quoted\" comment"; ce 350; id "WAC.001";"#);
+    let read = Epd::parse(&written).unwrap();
+    assert_eq!(read.operations, record.operations);
+    assert!(read.board == record.board);
+}
+
+#[test]
+fn epd_errors() {
+    assert_eq!(Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w").err(), Some(EpdError::MissingFields(2)));
+    assert!(matches!(Epd::parse("4k3/8/8/8/8/8/4P3/4K4 w - - bm e4;").err(), Some(EpdError::Fen(_))));
+    assert_eq!(Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - c0 \"open;").err(), Some(EpdError::UnterminatedString("c0".to_string())));
+    assert_eq!(Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - hmvc x;").err(),
+        Some(EpdError::BadOperand { opcode: "hmvc".to_string(), operand: "x".to_string() }));
+    // The record reads, only its moves do not
+    let record = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - bm e5;").unwrap();
+    assert_eq!(record.moves("bm"), Err(SanError::Illegal("e5".to_string())));
+}
+
+#[test]
+fn epd_read_file() {
+    let text = format!("# a comment\n\n{}\nbad\n", WAC_001);
+    let records = epd::read_epds(&text);
+    assert_eq!(records.iter().map(|(line, _)| *line).collect::<Vec<_>>(), [3, 4]);
+    assert!(records[0].1.is_ok());
+    assert!(records[1].1.is_err());
+}
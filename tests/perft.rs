@@ -0,0 +1,187 @@
+// Move generator regression tests: node counts of well-known perft positions, make_move/undo_move
+// restoring the board exactly, and the legal generator agreeing with make/undo filtering.
+// The deep counts are ignored by default, run them with `cargo test --release -- --ignored`
+use bytechess::board::{self, Board};
+use bytechess::util::perft;
+
+// Counts at depth 1, 2, 3, ... for each position
+const STANDARD: [(&str, &[u64]); 7] = [
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281, 4865609, 119060324]),
+    // Kiwipete
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862, 4085603, 193690690]),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238, 674624, 11030083]),
+    ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467, 422333, 15833292]),
+    // The same position mirrored, black to move
+    ("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1", &[6, 264, 9467, 422333, 15833292]),
+    ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379, 2103487, 89941194]),
+    ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", &[46, 2079, 89890, 3894594, 164075551]),
+];
+
+// Small positions around en passant, castling and promotions
+const EDGE_CASES: [(&str, &[u64]); 14] = [
+    // En passant that would expose the king, and en passant out of a discovered check
+    ("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1", &[18, 92, 1670, 10138, 185429, 1134888]),
+    ("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1", &[13, 102, 1266, 10276, 135655, 1015133]),
+    ("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1", &[15, 126, 1928, 13931, 206379, 1440467]),
+    // Castling that gives check, and castling rights lost to captures
+    ("5k2/8/8/8/8/8/8/4K2R w K - 0 1", &[15, 66, 1198, 6399, 120330, 661072]),
+    ("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", &[16, 71, 1286, 7418, 141077, 803711]),
+    ("r3k2r/1b4bq/8/8/8/8/7B/R3K2R w KQkq - 0 1", &[26, 1141, 27826, 1274206]),
+    ("r3k2r/8/3Q4/8/8/5q2/8/R3K2R b KQkq - 0 1", &[44, 1494, 50509, 1720476]),
+    // Promotions, under-promotions and stalemates
+    ("2K2r2/4P3/8/8/8/8/8/3k4 w - - 0 1", &[11, 133, 1442, 19174, 266199, 3821001]),
+    ("8/8/1P2K3/8/2n5/1q6/8/5k2 b - - 0 1", &[29, 165, 5160, 31961, 1004658]),
+    ("4k3/1P6/8/8/8/8/K7/8 w - - 0 1", &[9, 40, 472, 2661, 38983, 217342]),
+    ("8/P1k5/K7/8/8/8/8/8 w - - 0 1", &[6, 27, 273, 1329, 18135, 92683]),
+    ("K1k5/8/P7/8/8/8/8/8 w - - 0 1", &[2, 6, 13, 63, 382, 2217]),
+    ("8/k1P5/8/1K6/8/8/8/8 w - - 0 1", &[10, 25, 268, 926, 10857, 43261, 567584]),
+    ("8/8/2k5/5q2/5n2/8/5K2/8 b - - 0 1", &[37, 183, 6559, 23527]),
+];
+
+// Shredder-FEN castling, the rooks are named by file
+const CHESS960: [(&str, &[u64]); 4] = [
+    ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12189, 326672, 8146062, 227689589]),
+    ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", &[21, 807, 18002, 667366, 16253601]),
+    ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", &[20, 479, 10471, 273318, 6417013]),
+    ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", &[22, 593, 13440, 382958, 9183776]),
+];
+
+// Depths whose count stays under this run in the default test pass, every ply played out
+const SHALLOW_NODES: u64 = 500_000;
+
+fn check(positions: &[(&str, &[u64])], max_nodes: u64, bulk: bool) {
+    for (fen, counts) in positions {
+        let mut board = Board::from_fen(fen).unwrap();
+        for (depth, &expected) in counts.iter().enumerate().filter(|&(_, &n)| n <= max_nodes) {
+            assert_eq!(perft(&mut board, depth as u8 + 1, bulk), expected, "perft {} of {}", depth + 1, fen);
+        }
+    }
+}
+
+#[test]
+fn perft_standard() {
+    check(&STANDARD, SHALLOW_NODES, false);
+}
+
+#[test]
+fn perft_edge_cases() {
+    check(&EDGE_CASES, SHALLOW_NODES, false);
+}
+
+#[test]
+fn perft_chess960() {
+    check(&CHESS960, SHALLOW_NODES, false);
+}
+
+#[test]
+#[ignore]
+fn perft_standard_deep() {
+    check(&STANDARD, u64::MAX, true);
+}
+
+#[test]
+#[ignore]
+fn perft_edge_cases_deep() {
+    check(&EDGE_CASES, u64::MAX, true);
+}
+
+#[test]
+#[ignore]
+fn perft_chess960_deep() {
+    check(&CHESS960, u64::MAX, true);
+}
+
+// Plays every line `depth` plies deep and checks that each undo gives back the board it started from
+fn make_undo(board: &mut Board, depth: u8) {
+    if depth == 0 {
+        return;
+    }
+    board.gen_moves(true, false);
+    let moves = board.moves;
+    for mv in moves.iter() {
+        let before = board.clone();
+        board::make_move(board, mv).unwrap();
+        make_undo(board, depth - 1);
+        board::undo_move(board).unwrap();
+        // The move list is scratch space that the plies below overwrite, not part of the position
+        board.moves = before.moves;
+        assert!(*board == before, "make_move/undo_move of {} changed {}", mv, before);
+    }
+    if !board::is_check(board) {
+        let before = board.clone();
+        board::make_null_move(board).unwrap();
+        board::undo_null_move(board).unwrap();
+        board.moves = before.moves;
+        assert!(*board == before, "the null move changed {}", before);
+    }
+}
+
+#[test]
+fn make_undo_restores_board() {
+    for (fen, _) in STANDARD.iter().chain(&EDGE_CASES).chain(&CHESS960) {
+        make_undo(&mut Board::from_fen(fen).unwrap(), 3);
+    }
+}
+
+// The legal generator against the plain definition: pseudo-legal moves that do not leave the king attacked
+fn legal_matches_make_undo(board: &mut Board, depth: u8) {
+    let legal = board.legal_moves();
+    board.gen_moves(false, false);
+    let pseudo = board.moves;
+    let mut expected = Vec::new();
+    for mv in pseudo.iter() {
+        board::make_move(board, mv).unwrap();
+        if !board.king_is_attacked() {
+            expected.push(*mv);
+        }
+        board::undo_move(board).unwrap();
+    }
+    let mut generated: Vec<_> = legal.iter().copied().collect();
+    generated.sort_by_key(|mv| mv.info);
+    expected.sort_by_key(|mv| mv.info);
+    assert!(generated == expected, "legal moves of {} differ, generated {:?}, expected {:?}", board,
+        generated.iter().map(|mv| mv.to_string()).collect::<Vec<_>>(), expected.iter().map(|mv| mv.to_string()).collect::<Vec<_>>());
+    if depth > 1 {
+        for mv in legal.iter() {
+            board::make_move(board, mv).unwrap();
+            legal_matches_make_undo(board, depth - 1);
+            board::undo_move(board).unwrap();
+        }
+    }
+}
+
+#[test]
+fn legal_moves_match_make_undo() {
+    for (fen, _) in STANDARD.iter().chain(&EDGE_CASES).chain(&CHESS960) {
+        legal_matches_make_undo(&mut Board::from_fen(fen).unwrap(), 3);
+    }
+}
+
+// Captures and quiet moves are generated apart for the move picker, together they must be the full list
+fn split_matches_full(board: &mut Board, depth: u8) {
+    let legal = board.legal_moves();
+    board.gen_moves(true, true);
+    let mut split: Vec<_> = board.moves.iter().copied().collect();
+    assert!(split.iter().all(|mv| mv.flags() & 4 != 0), "a non-capture among the captures of {}", board);
+    board.gen_quiet_moves(true);
+    assert!(board.moves.iter().all(|mv| mv.flags() & 4 == 0), "a capture among the quiet moves of {}", board);
+    split.extend(board.moves.iter());
+    let mut full: Vec<_> = legal.iter().copied().collect();
+    split.sort_by_key(|mv| mv.info);
+    full.sort_by_key(|mv| mv.info);
+    assert!(split == full, "captures and quiet moves of {} differ from its legal moves", board);
+    if depth > 1 {
+        for mv in legal.iter() {
+            board::make_move(board, mv).unwrap();
+            split_matches_full(board, depth - 1);
+            board::undo_move(board).unwrap();
+        }
+    }
+}
+
+#[test]
+fn captures_and_quiets_make_up_legal_moves() {
+    for (fen, _) in STANDARD.iter().chain(&EDGE_CASES).chain(&CHESS960) {
+        split_matches_full(&mut Board::from_fen(fen).unwrap(), 3);
+    }
+}
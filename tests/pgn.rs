@@ -0,0 +1,95 @@
+// PGN reading and writing: tags, comments, NAGs, nested variations and results, and games that
+// read back the same after being written out
+use bytechess::pgn::{self, PgnError, PgnGame};
+use bytechess::san::SanError;
+
+const GAME: &str = r#"[Event "Casual \"blitz\" game"]
+[Site "?"]
+[Date "2024.01.02"]
+[Round "1"]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+[Annotator "C"]
+
+{Before the first move} 1. e4 e5 2. Nf3 $1 {The main move} (2. f4 exf4 (2... d5!? 3. exd5) 3. Nf3)
+2... Nc6?! 3. Bb5 a6 ; to the end of the line
+4. Ba4 Nf6 5. O-O 1-0
+"#;
+
+fn sans(line: &[pgn::PgnMove]) -> Vec<&str> {
+    line.iter().map(|m| m.san.as_str()).collect()
+}
+
+#[test]
+fn pgn_read_game() {
+    let game = pgn::read_game_str(GAME).unwrap();
+    assert_eq!(game.tag("Event"), Some("Casual \"blitz\" game"));
+    assert_eq!(game.tag("Annotator"), Some("C"));
+    assert_eq!(game.result, "1-0");
+    assert_eq!(game.comment.as_deref(), Some("Before the first move"));
+    assert_eq!(sans(&game.moves), ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O"]);
+
+    let nf3 = &game.moves[2];
+    assert_eq!(nf3.nags, [1]);
+    assert_eq!(nf3.comment.as_deref(), Some("The main move"));
+    // The King's Gambit replaces 2. Nf3, with 2... d5 nested in it in place of 2... exf4
+    assert_eq!(nf3.variations.len(), 1);
+    let gambit = &nf3.variations[0];
+    assert_eq!(sans(gambit), ["f4", "exf4", "Nf3"]);
+    assert_eq!(sans(&gambit[1].variations[0]), ["d5", "exd5"]);
+    assert_eq!(gambit[1].variations[0][0].nags, [5]);
+    assert_eq!(game.moves[3].nags, [6]);
+    assert_eq!(game.moves[5].comment.as_deref(), Some("to the end of the line"));
+}
+
+#[test]
+fn pgn_write_read_round_trip() {
+    let game = pgn::read_game_str(GAME).unwrap();
+    let written = game.to_string();
+    assert!(written.starts_with("[Event \"Casual \\\"blitz\\\" game\"]\n[Site \"?\"]"), "{}", written);
+    let movetext = written.split_whitespace().collect::<Vec<_>>().join(" ");
+    assert!(movetext.contains("(2. f4 exf4 (2... d5 $5 3. exd5) 3. Nf3) 2... Nc6 $6"), "{}", written);
+    assert!(written.lines().all(|line| line.len() <= 80), "{}", written);
+    assert_eq!(pgn::read_game_str(&written).unwrap(), game);
+}
+
+#[test]
+fn pgn_results() {
+    let text = "[Result \"0-1\"]\n\n1. f3 e5 2. g4 Qh4# 0-1\n\n[Result \"1/2-1/2\"]\n\n1. e4 1/2-1/2\n\n[Event \"Unfinished\"]\n\n1. d4 *\n";
+    let games: Vec<PgnGame> = pgn::read_games(text).into_iter().map(Result::unwrap).collect();
+    assert_eq!(games.len(), 3);
+    assert_eq!(games[0].moves.last().unwrap().san, "Qh4#");
+    assert_eq!(games.iter().map(|g| g.result_value()).collect::<Vec<_>>(), [Some(0.0), Some(0.5), None]);
+    assert_eq!(games[2].result, "*");
+}
+
+#[test]
+fn pgn_castling_with_zeros() {
+    let game = pgn::read_game_str("1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. 0-0 d5 5.d3 Bg4 6.Nc3 Qd7 7.Be3 0-0-0 *").unwrap();
+    assert_eq!(game.moves[6].san, "O-O");
+    assert_eq!(game.moves[13].san, "O-O-O");
+}
+
+#[test]
+fn pgn_from_fen() {
+    // Black to move from the FEN, numbered from its fullmove
+    let game = pgn::read_game_str("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 12\"]\n\n12... Kd7 13. e4 *").unwrap();
+    assert!(game.to_string().contains("12... Kd7 13. e4 *"));
+    // A fullmove of 0 is accepted and counted as the first move
+    let game = pgn::read_game_str("[FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 0\"]\n\n1. e4 *").unwrap();
+    assert!(game.to_string().contains("1. e4 *"));
+}
+
+#[test]
+fn pgn_errors() {
+    assert_eq!(pgn::read_game_str("1. e4 e5 2. Ke3 *"), Err(PgnError::Move { ply: 3, error: SanError::Illegal("Ke3".to_string()) }));
+    assert_eq!(pgn::read_game_str("1. e4 (1. d4 *"), Err(PgnError::UnbalancedVariation));
+    assert_eq!(pgn::read_game_str("1. e4 e5) *"), Err(PgnError::UnbalancedVariation));
+    assert_eq!(pgn::read_game_str("[Event unquoted]\n1. e4 *"), Err(PgnError::BadTag("Event unquoted".to_string())));
+    assert_eq!(pgn::read_game_str(""), Err(PgnError::NoGame));
+    // A bad game does not stop the ones after it
+    let games = pgn::read_games("[Event \"1\"]\n1. e5 *\n\n[Event \"2\"]\n1. e4 *\n");
+    assert!(games[0].is_err());
+    assert_eq!(games[1].as_ref().unwrap().tag("Event"), Some("2"));
+}
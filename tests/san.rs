@@ -0,0 +1,107 @@
+// SAN formatting and parsing: disambiguation, promotions, castling, check suffixes and rejected moves
+use bytechess::board::{self, Board};
+use bytechess::san::SanError;
+use bytechess::util::{Move, MoveFlag};
+
+// The SAN of a UCI move and the move `parse_san` reads back from it
+fn san(fen: &str, uci: &str) -> String {
+    let mut board = Board::from_fen(fen).unwrap();
+    let m = *board.legal_moves().iter().find(|m| m.to_string() == uci).unwrap();
+    let san = board.move_to_san(&m);
+    assert_eq!(board.parse_san(&san), Ok(m), "{} does not parse back in {}", san, fen);
+    san
+}
+
+fn parse(fen: &str, san: &str) -> Result<Move, SanError> {
+    Board::from_fen(fen).unwrap().parse_san(san)
+}
+
+#[test]
+fn san_disambiguation() {
+    // By file, by rank, and by both when neither alone tells the queens apart
+    assert_eq!(san("6k1/8/8/8/8/8/K7/R6R w - - 0 1", "a1d1"), "Rad1");
+    assert_eq!(san("6k1/8/8/R7/8/8/8/R5K1 w - - 0 1", "a1a3"), "R1a3");
+    assert_eq!(san("8/8/1k6/8/4Q2Q/8/8/K6Q w - - 0 1", "h4e1"), "Qh4e1");
+    // A rival that is pinned cannot make the move, so it does not need telling apart
+    assert_eq!(san("4k3/8/8/8/8/8/8/r1N1K1N1 w - - 0 1", "g1e2"), "Ne2");
+    assert_eq!(san("4k3/8/8/8/8/8/8/2N1K1N1 w - - 0 1", "g1e2"), "Nge2");
+}
+
+#[test]
+fn san_promotions_and_checks() {
+    let fen = "3r2k1/4P3/8/8/8/8/8/K7 w - - 0 1";
+    assert_eq!(san(fen, "e7e8q"), "e8=Q+");
+    assert_eq!(san(fen, "e7d8q"), "exd8=Q+");
+    assert_eq!(san(fen, "e7e8n"), "e8=N");
+    assert_eq!(parse(fen, "exd8Q"), parse(fen, "exd8=Q+"));
+    assert_eq!(san("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", "d1d8"), "Rd8#");
+    // Suffixes and annotations are ignored when reading
+    assert_eq!(parse("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", "Rd8+!?"), parse("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", "Rd8"));
+}
+
+fn castles(fen: &str) -> Vec<String> {
+    let mut board = Board::from_fen(fen).unwrap();
+    let castles: Vec<Move> = board.legal_moves().iter()
+        .filter(|m| m.flags() == MoveFlag::KingCastle as u8 || m.flags() == MoveFlag::QueenCastle as u8)
+        .copied()
+        .collect();
+    castles.iter().map(|m| {
+        let san = board.move_to_san(m);
+        assert_eq!(board.parse_san(&san), Ok(*m), "{} does not parse back in {}", san, fen);
+        san
+    }).collect()
+}
+
+#[test]
+fn san_castling() {
+    let mut both = castles("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+    both.sort();
+    assert_eq!(both, ["O-O", "O-O-O"]);
+    // Chess960, the king castles onto its own rook's square on the short side
+    let mut both = castles("4k3/8/8/8/8/8/8/1R2K1R1 w GB - 0 1");
+    both.sort();
+    assert_eq!(both, ["O-O", "O-O-O"]);
+    // Castling into check gets the suffix too
+    assert_eq!(castles("5k2/8/8/8/8/8/8/4K2R w K - 0 1"), ["O-O+"]);
+    let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+    assert_eq!(parse(fen, "0-0"), parse(fen, "O-O"));
+}
+
+#[test]
+fn san_rejected() {
+    assert_eq!(parse(board::STARTPOS_FEN, ""), Err(SanError::Empty));
+    assert_eq!(parse(board::STARTPOS_FEN, "e5"), Err(SanError::Illegal("e5".to_string())));
+    assert_eq!(parse(board::STARTPOS_FEN, "Ke2"), Err(SanError::Illegal("Ke2".to_string())));
+    assert_eq!(parse(board::STARTPOS_FEN, "O-O"), Err(SanError::Illegal("O-O".to_string())));
+    assert_eq!(parse(board::STARTPOS_FEN, "Zz9"), Err(SanError::Malformed("Zz9".to_string())));
+    assert_eq!(parse("6k1/8/8/8/8/8/K7/R6R w - - 0 1", "Rd1"), Err(SanError::Ambiguous("Rd1".to_string())));
+    assert_eq!(parse("8/8/1k6/8/4Q2Q/8/8/K6Q w - - 0 1", "Qhe1"), Err(SanError::Ambiguous("Qhe1".to_string())));
+    // A promotion must name its piece
+    assert_eq!(parse("3r2k1/4P3/8/8/8/8/8/K7 w - - 0 1", "e8"), Err(SanError::Illegal("e8".to_string())));
+}
+
+// Every legal move along every line of a few plies formats to SAN that reads back as the same move
+fn round_trip(board: &mut Board, depth: u8) {
+    let legal = board.legal_moves();
+    for m in legal.iter() {
+        let san = board.move_to_san(m);
+        assert_eq!(board.parse_san(&san), Ok(*m), "{} does not parse back in {}", san, board);
+        if depth > 1 {
+            board::make_move(board, m).unwrap();
+            round_trip(board, depth - 1);
+            board::undo_move(board).unwrap();
+        }
+    }
+}
+
+#[test]
+fn san_round_trip() {
+    for fen in [
+        board::STARTPOS_FEN,
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+    ] {
+        round_trip(&mut Board::from_fen(fen).unwrap(), 2);
+    }
+}
@@ -0,0 +1,35 @@
+// Search extensions on positions where they are known to apply
+use std::time::Instant;
+use bytechess::board::Board;
+use bytechess::{think, mate_in, RootResult, SearchLimits, SearchState};
+
+fn search(fen: &str, depth: i32) -> RootResult {
+    let mut state = SearchState::new(16);
+    state.search_info.quiet = true;
+    let mut board = Board::from_fen(fen).unwrap();
+    let limits = SearchLimits { depth: Some(depth), ..SearchLimits::default() };
+    let result = think(&mut board, &limits, Instant::now(), &mut state);
+    assert!(board.legal_moves().iter().any(|m| *m == result.best_move), "{} is not a move of {}", result.best_move, fen);
+    result
+}
+
+#[test]
+fn singular_moves_are_double_extended() {
+    // Black's king has to escort the pawn, with every king move but one letting the win slip. The
+    // mate is 17 plies away and only found at depth 9 because those forced moves are extended twice
+    let result = search("8/8/8/8/8/3k4/3p4/3K4 b - - 0 1", 9);
+    assert!(mate_in(result.score).is_some_and(|n| n > 0), "{}", result.score);
+}
+
+#[test]
+fn no_legal_moves_at_the_root() {
+    for (fen, mate) in [("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1", Some(0)), ("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", None)] {
+        let mut state = SearchState::new(16);
+        state.search_info.quiet = true;
+        let limits = SearchLimits { depth: Some(2), ..SearchLimits::default() };
+        let result = think(&mut Board::from_fen(fen).unwrap(), &limits, Instant::now(), &mut state);
+        assert_eq!(result.best_move.to_string(), "0000");
+        assert_eq!(mate_in(result.score), mate, "{}", fen);
+        assert!(mate.is_some() || result.score == 0);
+    }
+}
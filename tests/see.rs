@@ -0,0 +1,42 @@
+// Static exchange evaluation on small exchanges whose outcome is known
+use bytechess::board::{Board, BBPiece};
+use bytechess::PIECE_VALUES;
+
+// The SEE of a UCI move and the board it was played on, for the piece values at its phase
+fn see(fen: &str, uci: &str) -> (i32, Board) {
+    let mut board = Board::from_fen(fen).unwrap();
+    board.gen_moves(true, false);
+    let m = *board.moves.iter().find(|m| m.to_string() == uci).unwrap();
+    (board.see(&m), board)
+}
+
+fn value(board: &Board, piece: BBPiece) -> i32 {
+    PIECE_VALUES[piece as usize].taper(board.phase)
+}
+
+#[test]
+fn see_undefended_capture() {
+    let (score, board) = see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5");
+    assert_eq!(score, value(&board, BBPiece::Pawn));
+    let (score, board) = see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6");
+    assert_eq!(score, value(&board, BBPiece::Pawn));
+}
+
+#[test]
+fn see_losing_capture() {
+    // Knight for a pawn once the bishop, knight and the x-rayed queen join in
+    let (score, board) = see("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5");
+    assert_eq!(score, value(&board, BBPiece::Pawn) - value(&board, BBPiece::Knight));
+    let (score, board) = see("4k3/8/3p4/4p3/8/8/8/4QK2 w - - 0 1", "e1e5");
+    assert_eq!(score, value(&board, BBPiece::Pawn) - value(&board, BBPiece::Queen));
+    // The new queen is taken, the pawn is lost
+    let (score, board) = see("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q");
+    assert_eq!(score, -value(&board, BBPiece::Pawn));
+}
+
+#[test]
+fn see_xray_recapture() {
+    // The second rook recaptures through the first one
+    let (score, board) = see("4k3/4r3/8/4p3/8/8/4R3/4RK2 w - - 0 1", "e2e5");
+    assert_eq!(score, value(&board, BBPiece::Pawn));
+}